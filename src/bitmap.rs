@@ -0,0 +1,232 @@
+//! Bit-level access to string values. Bits are numbered from the most
+//! significant bit of the first byte, like Redis does, so that bit 0 is the
+//! leftmost bit of the string.
+
+use crate::util::parse_int;
+
+/// Bits are addressed with offsets below 2^32, which keeps strings grown by
+/// them under the 512MB limit Redis puts on string values.
+pub const MAX_BIT_OFFSET: u64 = (1 << 32) - 1;
+
+pub const INVALID_OFFSET: &str = "ERR bit offset is not an integer or out of range";
+
+/// Parses a bit offset, which must be in `0..=MAX_BIT_OFFSET`.
+pub fn parse_offset(arg: &[u8]) -> Option<u64> {
+    parse_int(arg)
+        .filter(|offset| (0..=MAX_BIT_OFFSET as i64).contains(offset))
+        .map(|offset| offset as u64)
+}
+
+pub fn get_bit(bytes: &[u8], offset: u64) -> bool {
+    let Some(byte) = bytes.get((offset / 8) as usize) else {
+        return false;
+    };
+    byte & (0x80 >> (offset % 8)) != 0
+}
+
+/// Sets the bit at `offset`, padding the string with zero bytes to reach it,
+/// and returns its previous value.
+pub fn set_bit(bytes: &mut Vec<u8>, offset: u64, bit: bool) -> bool {
+    let index = (offset / 8) as usize;
+    if index >= bytes.len() {
+        bytes.resize(index + 1, 0);
+    }
+    let mask = 0x80 >> (offset % 8);
+    let old = bytes[index] & mask != 0;
+    if bit {
+        bytes[index] |= mask;
+    } else {
+        bytes[index] &= !mask;
+    }
+    old
+}
+
+/// Clamps an inclusive range of possibly negative indexes to `len` units,
+/// bytes or bits, the way BITCOUNT and BITPOS do: out of range bounds are
+/// moved to the closest end instead of emptying the range.
+pub fn clamp_range(start: i64, end: i64, len: u64) -> Option<(u64, u64)> {
+    let len = len as i64;
+    let start = if start < 0 { start + len } else { start }.max(0);
+    let end = if end < 0 { end + len } else { end }.max(0);
+    let end = end.min(len - 1);
+    (len > 0 && start <= end).then_some((start as u64, end as u64))
+}
+
+/// Number of set bits between the bit offsets `start` and `end` included.
+pub fn count(bytes: &[u8], start: u64, end: u64) -> u64 {
+    let (first, last) = ((start / 8) as usize, (end / 8) as usize);
+    let head = 0xffu8 >> (start % 8);
+    let tail = 0xffu8 << (7 - end % 8);
+    if first == last {
+        return (bytes[first] & head & tail).count_ones() as u64;
+    }
+    let middle: u64 = bytes[first + 1..last]
+        .iter()
+        .map(|b| b.count_ones() as u64)
+        .sum();
+    (bytes[first] & head).count_ones() as u64 + middle + (bytes[last] & tail).count_ones() as u64
+}
+
+/// Offset of the first bit set to `bit` between the bit offsets `start` and
+/// `end` included.
+pub fn position(bytes: &[u8], bit: bool, start: u64, end: u64) -> Option<u64> {
+    // whole bytes without the bit are skipped at once
+    let skip = if bit { 0x00 } else { 0xff };
+    let mut offset = start;
+    while offset <= end {
+        if offset.is_multiple_of(8) && offset + 7 <= end && bytes[(offset / 8) as usize] == skip {
+            offset += 8;
+            continue;
+        }
+        if get_bit(bytes, offset) == bit {
+            return Some(offset);
+        }
+        offset += 1;
+    }
+    None
+}
+
+/// BITOP operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+impl BitOp {
+    pub fn parse(arg: &[u8]) -> Option<BitOp> {
+        match arg.to_ascii_lowercase().as_slice() {
+            b"and" => Some(BitOp::And),
+            b"or" => Some(BitOp::Or),
+            b"xor" => Some(BitOp::Xor),
+            b"not" => Some(BitOp::Not),
+            _ => None,
+        }
+    }
+
+    /// Combines the sources byte by byte, the shorter ones being padded
+    /// with zeros up to the length of the longest.
+    pub fn apply(self, sources: &[&[u8]]) -> Vec<u8> {
+        let len = sources.iter().map(|s| s.len()).max().unwrap_or(0);
+        let byte = |source: &[u8], i: usize| source.get(i).copied().unwrap_or(0);
+        (0..len)
+            .map(|i| {
+                let mut bytes = sources.iter().map(|s| byte(s, i));
+                let first = bytes.next().unwrap_or(0);
+                match self {
+                    BitOp::And => bytes.fold(first, |acc, b| acc & b),
+                    BitOp::Or => bytes.fold(first, |acc, b| acc | b),
+                    BitOp::Xor => bytes.fold(first, |acc, b| acc ^ b),
+                    BitOp::Not => !first,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Type of a BITFIELD integer, like `i16` or `u8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+impl FieldType {
+    /// Parses `i1` to `i64` or `u1` to `u63`; `u64` wouldn't fit in the
+    /// signed integers of the replies.
+    pub fn parse(arg: &[u8]) -> Option<FieldType> {
+        let (signed, bits) = match arg {
+            [b'i' | b'I', bits @ ..] => (true, bits),
+            [b'u' | b'U', bits @ ..] => (false, bits),
+            _ => return None,
+        };
+        let bits = parse_int(bits)?;
+        let max = if signed { 64 } else { 63 };
+        (1..=max).contains(&bits).then_some(FieldType {
+            signed,
+            bits: bits as u32,
+        })
+    }
+
+    fn min(self) -> i128 {
+        if self.signed {
+            -(1 << (self.bits - 1))
+        } else {
+            0
+        }
+    }
+
+    fn max(self) -> i128 {
+        if self.signed {
+            (1 << (self.bits - 1)) - 1
+        } else {
+            (1 << self.bits) - 1
+        }
+    }
+
+    /// Brings `value` into the range of the type according to `overflow`,
+    /// `None` if it doesn't fit and overflows are to fail.
+    pub fn fit(self, value: i128, overflow: Overflow) -> Option<i64> {
+        if (self.min()..=self.max()).contains(&value) {
+            return Some(value as i64);
+        }
+        match overflow {
+            Overflow::Fail => None,
+            Overflow::Sat => Some(value.clamp(self.min(), self.max()) as i64),
+            Overflow::Wrap => {
+                let wrapped = value & ((1 << self.bits) - 1);
+                if self.signed && wrapped > self.max() {
+                    Some((wrapped - (1 << self.bits)) as i64)
+                } else {
+                    Some(wrapped as i64)
+                }
+            }
+        }
+    }
+
+    /// Reads an integer of this type at the bit offset `offset`, bits past
+    /// the end of the string reading as zeros.
+    pub fn read(self, bytes: &[u8], offset: u64) -> i64 {
+        let mut value: u64 = 0;
+        for i in 0..self.bits as u64 {
+            value = (value << 1) | get_bit(bytes, offset + i) as u64;
+        }
+        if self.signed && self.bits < 64 && (value >> (self.bits - 1)) & 1 == 1 {
+            // sign extension
+            value |= u64::MAX << self.bits;
+        }
+        value as i64
+    }
+
+    /// Writes `value`, which must fit in the type, at the bit offset
+    /// `offset`, growing the string if needed.
+    pub fn write(self, bytes: &mut Vec<u8>, offset: u64, value: i64) {
+        let value = value as u64;
+        for i in 0..self.bits as u64 {
+            let bit = (value >> (self.bits as u64 - 1 - i)) & 1 == 1;
+            set_bit(bytes, offset + i, bit);
+        }
+    }
+}
+
+/// What BITFIELD SET and INCRBY do with values out of the range of the type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    #[default]
+    Wrap,
+    Sat,
+    Fail,
+}
+
+impl Overflow {
+    pub fn parse(arg: &[u8]) -> Option<Overflow> {
+        match arg.to_ascii_lowercase().as_slice() {
+            b"wrap" => Some(Overflow::Wrap),
+            b"sat" => Some(Overflow::Sat),
+            b"fail" => Some(Overflow::Fail),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    Data, Value,
+    client::Peer,
+    db::Db,
+    util::{parse_float, parse_int},
+};
+
+/// Tries to serve a blocked command against the keyspace, returning its
+/// reply or `None` if it still has to wait.
+pub type Attempt = Box<dyn FnMut(&mut Db) -> Option<Value> + Send>;
+
+/// How often a parked client checks whether its peer hung up.
+const HANGUP_CHECK_PERIOD: Duration = Duration::from_millis(100);
+
+/// A client parked until one of its keys can serve it or its timeout runs out.
+pub struct Waiter {
+    keys: Vec<Vec<u8>>,
+    attempt: Mutex<Attempt>,
+    reply: Mutex<Option<Value>>,
+    served: Condvar,
+}
+
+impl Waiter {
+    fn new(keys: Vec<Vec<u8>>, attempt: Attempt) -> Arc<Self> {
+        Arc::new(Self {
+            keys,
+            attempt: Mutex::new(attempt),
+            reply: Mutex::new(None),
+            served: Condvar::new(),
+        })
+    }
+
+    pub fn keys(&self) -> &[Vec<u8>] {
+        &self.keys
+    }
+
+    /// Runs the attempt of the waiter, waking it up with the reply on success.
+    /// Returns whether the waiter is done with.
+    ///
+    /// Whether its client hung up is left to the waiter's own thread, the
+    /// socket can't be looked at from here without racing it.
+    pub fn try_serve(&self, db: &mut Db) -> bool {
+        let reply = (self.attempt.lock().unwrap())(db);
+        match reply {
+            Some(reply) => {
+                *self.reply.lock().unwrap() = Some(reply);
+                self.served.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sleeps until the waiter is served, `deadline` passes or `peer` hangs
+    /// up. Runs on the thread of the blocked connection, the only one that
+    /// touches its socket.
+    fn wait(&self, peer: &Peer, deadline: Option<Instant>) -> Option<Value> {
+        let mut reply = self.reply.lock().unwrap();
+        while reply.is_none() {
+            let mut period = HANGUP_CHECK_PERIOD;
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                period = period.min(deadline - now);
+            }
+            reply = self.served.wait_timeout(reply, period).unwrap().0;
+            if reply.is_none() && !peer.is_connected() {
+                break;
+            }
+        }
+        reply.take()
+    }
+}
+
+/// Clients blocked on the keys of a keyspace, queued per key in the order
+/// they blocked so that the first one to block is the first one served.
+#[derive(Default)]
+pub struct BlockedClients {
+    pub(crate) by_key: HashMap<Vec<u8>, VecDeque<Arc<Waiter>>>,
+    pub(crate) ready_keys: VecDeque<Vec<u8>>,
+    pub(crate) serving: bool,
+}
+
+impl std::fmt::Debug for BlockedClients {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlockedClients")
+            .field("keys", &self.by_key.len())
+            .field("ready_keys", &self.ready_keys.len())
+            .finish()
+    }
+}
+
+/// Parses a blocking timeout given in (possibly fractional) seconds, `None`
+/// meaning block forever.
+pub fn parse_timeout(arg: &[u8]) -> Result<Option<Duration>, &'static str> {
+    let Some(timeout) = parse_float(arg).filter(|t| t.is_finite()) else {
+        return Err("ERR timeout is not a float or out of range");
+    };
+    if timeout < 0.0 {
+        return Err("ERR timeout is negative");
+    }
+    if timeout == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(timeout)
+        .map(Some)
+        .map_err(|_| "ERR timeout is out of range")
+}
+
+/// Parses the BLOCK argument of XREAD and XREADGROUP, given in
+/// milliseconds, `None` meaning block forever.
+pub fn parse_timeout_ms(arg: &[u8]) -> Result<Option<Duration>, &'static str> {
+    match parse_int(arg) {
+        Some(0) => Ok(None),
+        Some(ms) if ms > 0 => Ok(Some(Duration::from_millis(ms as u64))),
+        Some(_) => Err("ERR timeout is negative"),
+        None => Err("ERR timeout is not an integer or out of range"),
+    }
+}
+
+/// Serves a blocking command: runs `attempt` right away and, if it can't be
+/// served yet, parks the calling thread until a write to one of `keys` lets
+/// it through or `timeout` elapses, in which case `None` is returned. `None`
+/// is also returned as soon as `peer` hangs up.
+pub fn block_on(
+    data: &Data,
+    keys: Vec<Vec<u8>>,
+    timeout: Option<Duration>,
+    peer: Peer,
+    mut attempt: Attempt,
+) -> Option<Value> {
+    // a timeout too far away to be represented is as good as none
+    let deadline = timeout.and_then(|t| Instant::now().checked_add(t));
+    let waiter = {
+        let mut lock = data.lock().unwrap();
+        if let Some(reply) = attempt(&mut lock) {
+            return Some(reply);
+        }
+        let waiter = Waiter::new(keys, attempt);
+        lock.block(waiter.clone());
+        waiter
+    };
+    if let Some(reply) = waiter.wait(&peer, deadline) {
+        return Some(reply);
+    }
+    // the waiter may have been served between the wake up and this lock
+    let mut lock = data.lock().unwrap();
+    lock.unblock(&waiter);
+    waiter.reply.lock().unwrap().take()
+}
@@ -0,0 +1,86 @@
+use std::{
+    io::{ErrorKind, Result, Write},
+    net::TcpStream,
+};
+
+use crate::{Protocol, Value};
+
+/// State of a single connection: who it is, what it negotiated and where its
+/// replies go.
+pub struct Client {
+    pub id: u64,
+    pub protocol: Protocol,
+    pub name: Option<Vec<u8>>,
+    /// Index of the database commands run against, chosen with SELECT.
+    pub db: usize,
+    stream: Box<dyn Write + Send>,
+    /// The socket of the connection, to notice a hang up while blocked.
+    socket: Option<TcpStream>,
+}
+
+/// A handle on the socket of a connection telling whether the other end is
+/// still there.
+#[derive(Debug)]
+pub struct Peer(Option<TcpStream>);
+
+impl Peer {
+    /// Whether the peer is still connected. This peeks at the socket without
+    /// blocking by switching it to nonblocking mode for a moment, a mode all
+    /// the clones of the socket share, so it must only run on the thread of
+    /// the connection while it isn't reading, that is while it is blocked.
+    pub fn is_connected(&self) -> bool {
+        let Some(socket) = &self.0 else {
+            return true;
+        };
+        if socket.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let connected = match socket.peek(&mut [0]) {
+            Ok(0) => false,
+            Ok(_) => true,
+            Err(err) => err.kind() == ErrorKind::WouldBlock,
+        };
+        let _ = socket.set_nonblocking(false);
+        connected
+    }
+}
+
+impl Client {
+    pub fn new(id: u64, stream: Box<dyn Write + Send>) -> Self {
+        Self {
+            id,
+            protocol: Protocol::default(),
+            name: None,
+            db: 0,
+            stream,
+            socket: None,
+        }
+    }
+
+    /// Lets blocking commands watch `socket` for the client hanging up.
+    pub fn with_socket(mut self, socket: TcpStream) -> Self {
+        self.socket = Some(socket);
+        self
+    }
+
+    pub fn peer(&self) -> Peer {
+        Peer(self.socket.as_ref().and_then(|s| s.try_clone().ok()))
+    }
+
+    /// Encodes `value` for the protocol this connection speaks and sends it.
+    pub fn send(&mut self, value: &Value) -> Result<()> {
+        let resp = value.encode(self.protocol);
+        self.stream.write_all(&resp)?;
+        self.stream.flush()
+    }
+}
+
+impl Write for Client {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.stream.flush()
+    }
+}
@@ -0,0 +1,440 @@
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    sync::Arc,
+    sync::{Mutex, MutexGuard},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    Databases, NOT_AN_INTEGER,
+    blocking::{BlockedClients, Waiter},
+    dict::Dict,
+    object::{Hash, Object, WrongType},
+    set::Set,
+    stream::Stream,
+    util::parse_int,
+    zset::SortedSet,
+};
+
+/// Current unix time in milliseconds, the unit every expire is stored in.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Number of databases when the server isn't told otherwise, like Redis.
+pub const DEFAULT_DATABASES: usize = 16;
+
+/// Most databases the server can be started with. Each one is a keyspace
+/// and a lock of its own, allocated up front.
+pub const MAX_DATABASES: usize = 1024;
+
+/// Keys with an expire looked at per round of `active_expire_cycle`.
+const ACTIVE_EXPIRE_SAMPLES: usize = 20;
+
+/// Another round is run while more than this percentage of the sampled keys
+/// turned out expired.
+const ACTIVE_EXPIRE_STALE_PERCENT: usize = 25;
+
+/// Creates `count` empty databases.
+pub fn new_databases(count: usize) -> Databases {
+    Arc::new(
+        (0..count)
+            .map(|_| Arc::new(Mutex::new(Db::new())))
+            .collect(),
+    )
+}
+
+/// Parses the index of one of `dbs`.
+pub fn parse_db_index(arg: &[u8], dbs: &Databases) -> Result<usize, &'static str> {
+    let index = parse_int(arg).ok_or(NOT_AN_INTEGER)?;
+    usize::try_from(index)
+        .ok()
+        .filter(|index| *index < dbs.len())
+        .ok_or("ERR DB index is out of range")
+}
+
+/// Locks the databases at `a` and `b`, which must differ, always in index
+/// order so that two commands locking the same pair can't deadlock.
+pub fn lock_pair(dbs: &Databases, a: usize, b: usize) -> (MutexGuard<'_, Db>, MutexGuard<'_, Db>) {
+    if a < b {
+        let first = dbs[a].lock().unwrap();
+        (first, dbs[b].lock().unwrap())
+    } else {
+        let second = dbs[b].lock().unwrap();
+        (dbs[a].lock().unwrap(), second)
+    }
+}
+
+/// A keyspace: the stored values plus the absolute expire time of the keys
+/// that have one.
+///
+/// Expired keys are removed lazily whenever they are looked up, and
+/// `active_expire_cycle` takes care of the ones nobody asks for anymore.
+///
+/// The keyspace also keeps track of the clients blocked on its keys, so that
+/// the command that makes a key ready can serve them before anyone else gets
+/// to see the new data.
+#[derive(Debug, Default)]
+pub struct Db {
+    entries: Dict<Vec<u8>, Object>,
+    expires: Dict<Vec<u8>, u64>,
+    blocked: BlockedClients,
+}
+
+impl Db {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deletes `key` if its time is up, returning whether it did.
+    fn expire_if_needed(&mut self, key: &[u8]) -> bool {
+        match self.expires.get(key) {
+            Some(at) if *at <= now_ms() => {
+                self.expires.remove(key);
+                self.entries.remove(key);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Option<&Object> {
+        self.expire_if_needed(key);
+        self.entries.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut Object> {
+        self.expire_if_needed(key);
+        self.entries.get_mut(key)
+    }
+
+    pub fn get_string(&mut self, key: &[u8]) -> Result<Option<Cow<'_, [u8]>>, WrongType> {
+        self.get(key).map(Object::as_string).transpose()
+    }
+
+    pub fn get_string_mut(&mut self, key: &[u8]) -> Result<Option<&mut Vec<u8>>, WrongType> {
+        self.get_mut(key).map(Object::as_string_mut).transpose()
+    }
+
+    /// The string stored at `key`, created empty if the key doesn't exist.
+    pub fn get_or_create_string(&mut self, key: &[u8]) -> Result<&mut Vec<u8>, WrongType> {
+        if !self.contains_key(key) {
+            self.entries
+                .insert(key.to_vec(), Object::String(Vec::new()));
+        }
+        self.entries.get_mut(key).unwrap().as_string_mut()
+    }
+
+    pub fn get_list(&mut self, key: &[u8]) -> Result<Option<&VecDeque<Vec<u8>>>, WrongType> {
+        self.get(key).map(Object::as_list).transpose()
+    }
+
+    pub fn get_list_mut(
+        &mut self,
+        key: &[u8],
+    ) -> Result<Option<&mut VecDeque<Vec<u8>>>, WrongType> {
+        self.get_mut(key).map(Object::as_list_mut).transpose()
+    }
+
+    /// The list stored at `key`, created empty if the key doesn't exist.
+    /// Callers that may leave it empty have to `remove_if_empty` afterwards.
+    pub fn get_or_create_list(&mut self, key: &[u8]) -> Result<&mut VecDeque<Vec<u8>>, WrongType> {
+        if !self.contains_key(key) {
+            self.entries
+                .insert(key.to_vec(), Object::List(VecDeque::new()));
+        }
+        self.entries.get_mut(key).unwrap().as_list_mut()
+    }
+
+    pub fn get_hash(&mut self, key: &[u8]) -> Result<Option<&Hash>, WrongType> {
+        self.get(key).map(Object::as_hash).transpose()
+    }
+
+    pub fn get_hash_mut(&mut self, key: &[u8]) -> Result<Option<&mut Hash>, WrongType> {
+        self.get_mut(key).map(Object::as_hash_mut).transpose()
+    }
+
+    /// The hash stored at `key`, created empty if the key doesn't exist.
+    pub fn get_or_create_hash(&mut self, key: &[u8]) -> Result<&mut Hash, WrongType> {
+        if !self.contains_key(key) {
+            self.entries.insert(key.to_vec(), Object::Hash(Hash::new()));
+        }
+        self.entries.get_mut(key).unwrap().as_hash_mut()
+    }
+
+    pub fn get_set(&mut self, key: &[u8]) -> Result<Option<&Set>, WrongType> {
+        self.get(key).map(Object::as_set).transpose()
+    }
+
+    /// The sets stored at `keys`, borrowed all at once for the commands that
+    /// read several side by side.
+    pub fn get_sets(&mut self, keys: &[&[u8]]) -> Result<Vec<Option<&Set>>, WrongType> {
+        for key in keys {
+            self.expire_if_needed(key);
+        }
+        keys.iter()
+            .map(|key| self.entries.get(*key).map(Object::as_set).transpose())
+            .collect()
+    }
+
+    pub fn get_set_mut(&mut self, key: &[u8]) -> Result<Option<&mut Set>, WrongType> {
+        self.get_mut(key).map(Object::as_set_mut).transpose()
+    }
+
+    /// The set stored at `key`, created empty if the key doesn't exist.
+    pub fn get_or_create_set(&mut self, key: &[u8]) -> Result<&mut Set, WrongType> {
+        if !self.contains_key(key) {
+            self.entries.insert(key.to_vec(), Object::Set(Set::new()));
+        }
+        self.entries.get_mut(key).unwrap().as_set_mut()
+    }
+
+    pub fn get_zset(&mut self, key: &[u8]) -> Result<Option<&SortedSet>, WrongType> {
+        self.get(key).map(Object::as_zset).transpose()
+    }
+
+    pub fn get_zset_mut(&mut self, key: &[u8]) -> Result<Option<&mut SortedSet>, WrongType> {
+        self.get_mut(key).map(Object::as_zset_mut).transpose()
+    }
+
+    /// The sorted set stored at `key`, created empty if the key doesn't
+    /// exist.
+    pub fn get_or_create_zset(&mut self, key: &[u8]) -> Result<&mut SortedSet, WrongType> {
+        if !self.contains_key(key) {
+            self.entries
+                .insert(key.to_vec(), Object::ZSet(SortedSet::new()));
+        }
+        self.entries.get_mut(key).unwrap().as_zset_mut()
+    }
+
+    pub fn get_stream(&mut self, key: &[u8]) -> Result<Option<&Stream>, WrongType> {
+        self.get(key).map(Object::as_stream).transpose()
+    }
+
+    pub fn get_stream_mut(&mut self, key: &[u8]) -> Result<Option<&mut Stream>, WrongType> {
+        self.get_mut(key).map(Object::as_stream_mut).transpose()
+    }
+
+    /// Deletes `key` if it holds a collection without elements.
+    pub fn remove_if_empty(&mut self, key: &[u8]) {
+        if self
+            .entries
+            .get(key)
+            .is_some_and(Object::is_empty_collection)
+        {
+            self.remove(key);
+        }
+    }
+
+    pub fn contains_key(&mut self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Stores `value` under `key`, dropping whatever expire the key had.
+    pub fn insert(&mut self, key: Vec<u8>, value: Object) {
+        self.expires.remove(&key);
+        self.entries.insert(key.clone(), value);
+        self.signal_ready(&key);
+    }
+
+    /// Stores `value` under `key`, keeping the expire of a live key.
+    pub fn insert_keep_ttl(&mut self, key: Vec<u8>, value: Object) {
+        self.expire_if_needed(&key);
+        self.entries.insert(key.clone(), value);
+        self.signal_ready(&key);
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Object> {
+        if self.expire_if_needed(key) {
+            return None;
+        }
+        self.expires.remove(key);
+        self.entries.remove(key)
+    }
+
+    /// Absolute expire time of `key` in unix milliseconds, if it has one.
+    pub fn expire_at(&mut self, key: &[u8]) -> Option<u64> {
+        self.expire_if_needed(key);
+        self.expires.get(key).copied()
+    }
+
+    /// Makes `key` expire at `at` unix milliseconds. A time in the past
+    /// deletes the key right away. Returns false if the key doesn't exist.
+    pub fn set_expire(&mut self, key: &[u8], at: u64) -> bool {
+        if !self.contains_key(key) {
+            return false;
+        }
+        if at <= now_ms() {
+            self.remove(key);
+        } else {
+            self.expires.insert(key.to_vec(), at);
+        }
+        true
+    }
+
+    /// Drops the expire of `key`, returning whether it had one.
+    pub fn persist(&mut self, key: &[u8]) -> bool {
+        self.expire_if_needed(key);
+        self.expires.remove(key).is_some()
+    }
+
+    /// Amount of keys, including expired ones not collected yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The live keys and their values, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Object)> {
+        let now = now_ms();
+        self.entries
+            .iter()
+            .filter(move |(key, _)| self.expires.get(*key).is_none_or(|at| *at > now))
+    }
+
+    /// One SCAN step over the live keys, see `Dict::scan`.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(&Vec<u8>, &Object)) -> u64 {
+        let now = now_ms();
+        self.entries.scan(cursor, count, |key, value| {
+            if self.expires.get(key).is_none_or(|at| *at > now) {
+                visit(key, value);
+            }
+        })
+    }
+
+    /// A random live key, `None` if the keyspace is empty. Expired keys hit
+    /// on the way are deleted and another pick is made.
+    pub fn random_key(&mut self) -> Option<Vec<u8>> {
+        while !self.entries.is_empty() {
+            let key = self.entries.random()?.0.clone();
+            if !self.expire_if_needed(&key) {
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    /// Deletes every key and hands the values back, so that the caller can
+    /// choose where they are freed. Blocked clients stay where they are.
+    pub fn flush(&mut self) -> Dict<Vec<u8>, Object> {
+        self.expires = Dict::new();
+        std::mem::take(&mut self.entries)
+    }
+
+    /// Exchanges the keys of two databases, for SWAPDB. Blocked clients
+    /// stay with their database and get served if what they wait for is
+    /// there now.
+    pub fn swap_contents(&mut self, other: &mut Db) {
+        std::mem::swap(&mut self.entries, &mut other.entries);
+        std::mem::swap(&mut self.expires, &mut other.expires);
+        self.signal_blocked_keys();
+        other.signal_blocked_keys();
+    }
+
+    fn signal_blocked_keys(&mut self) {
+        let ready: Vec<Vec<u8>> = self
+            .blocked
+            .by_key
+            .keys()
+            .filter(|key| self.entries.contains_key(*key))
+            .cloned()
+            .collect();
+        for key in ready {
+            self.signal_ready(&key);
+        }
+    }
+
+    /// Parks `waiter` on each of its keys, behind the clients already there.
+    pub fn block(&mut self, waiter: Arc<Waiter>) {
+        for key in waiter.keys() {
+            self.blocked
+                .by_key
+                .entry(key.clone())
+                .or_default()
+                .push_back(waiter.clone());
+        }
+    }
+
+    /// Takes `waiter` out of the queues of all of its keys.
+    pub fn unblock(&mut self, waiter: &Arc<Waiter>) {
+        for key in waiter.keys() {
+            if let Some(queue) = self.blocked.by_key.get_mut(key) {
+                queue.retain(|w| !Arc::ptr_eq(w, waiter));
+                if queue.is_empty() {
+                    self.blocked.by_key.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Tells the clients blocked on `key` that it may be able to serve them
+    /// now. Called by every command that adds data to a key.
+    ///
+    /// Waiters are tried in the order they blocked. Serving one may make
+    /// other keys ready (BLMOVE pushes to its destination), those are queued
+    /// and handled by the outermost call, so this never recurses.
+    pub fn signal_ready(&mut self, key: &[u8]) {
+        if !self.blocked.by_key.contains_key(key) {
+            return;
+        }
+        self.blocked.ready_keys.push_back(key.to_vec());
+        if self.blocked.serving {
+            return;
+        }
+        self.blocked.serving = true;
+        while let Some(key) = self.blocked.ready_keys.pop_front() {
+            let waiters: Vec<Arc<Waiter>> = match self.blocked.by_key.get(&key) {
+                Some(queue) => queue.iter().cloned().collect(),
+                None => continue,
+            };
+            for waiter in waiters {
+                if waiter.try_serve(self) {
+                    self.unblock(&waiter);
+                }
+            }
+        }
+        self.blocked.serving = false;
+    }
+
+    /// Removes keys whose expire is in the past and returns how many went
+    /// away. Run periodically by the server so that keys that are never
+    /// accessed again don't stay in memory forever.
+    ///
+    /// Like in Redis the expires are sampled rather than walked: rounds of
+    /// `ACTIVE_EXPIRE_SAMPLES` random keys go on while more than
+    /// `ACTIVE_EXPIRE_STALE_PERCENT` of them were expired, which means many
+    /// more probably are, and until `deadline`.
+    pub fn active_expire_cycle(&mut self, deadline: Instant) -> usize {
+        let mut removed = 0;
+        loop {
+            let now = now_ms();
+            let mut expired = Vec::new();
+            for _ in 0..ACTIVE_EXPIRE_SAMPLES.min(self.expires.len()) {
+                if let Some((key, at)) = self.expires.random()
+                    && *at <= now
+                {
+                    expired.push(key.clone());
+                }
+            }
+            // a key drawn twice is only removed once
+            for key in &expired {
+                if self.expires.remove(key).is_some() {
+                    self.entries.remove(key);
+                    removed += 1;
+                }
+            }
+            if expired.len() * 100 <= ACTIVE_EXPIRE_SAMPLES * ACTIVE_EXPIRE_STALE_PERCENT
+                || Instant::now() >= deadline
+            {
+                return removed;
+            }
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! The hash table behind the keyspace and the hash, set and sorted set
+//! types.
+//!
+//! It is a plain chained table whose size is always a power of two, like the
+//! `dict` of Redis, because that is what the SCAN family needs: the buckets
+//! can be walked with a cursor that stays valid while the table grows or
+//! shrinks in between calls, without any server side state.
+
+use std::{
+    borrow::Borrow,
+    fmt::Debug,
+    hash::{BuildHasher, Hash, RandomState},
+    mem,
+};
+
+use crate::util::random_u64;
+
+/// Buckets of the smallest table that holds anything.
+const MIN_BUCKETS: usize = 4;
+
+/// The table shrinks once it is less than 1/`MIN_FILL` full.
+const MIN_FILL: usize = 8;
+
+#[derive(Clone)]
+pub struct Dict<K, V> {
+    buckets: Vec<Vec<(K, V)>>,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl<K, V> Default for Dict<K, V> {
+    fn default() -> Self {
+        Self {
+            buckets: Vec::new(),
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> Dict<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn bucket_of<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        self.hasher.hash_one(key) as usize & (self.buckets.len() - 1)
+    }
+
+    fn bucket<Q>(&self, key: &Q) -> Option<&Vec<(K, V)>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        Some(&self.buckets[self.bucket_of(key)])
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let bucket = self.bucket(key)?;
+        bucket
+            .iter()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let index = self.bucket_of(key);
+        self.buckets[index]
+            .iter_mut()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Stores `value` under `key`, returning the value it replaces.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(old) = self.get_mut(&key) {
+            return Some(mem::replace(old, value));
+        }
+        if self.len >= self.buckets.len() {
+            self.resize((self.buckets.len() * 2).max(MIN_BUCKETS));
+        }
+        let index = self.bucket_of(&key);
+        self.buckets[index].push((key, value));
+        self.len += 1;
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let index = self.bucket_of(key);
+        let bucket = &mut self.buckets[index];
+        let position = bucket.iter().position(|(k, _)| k.borrow() == key)?;
+        let (_, value) = bucket.swap_remove(position);
+        self.len -= 1;
+        if self.len == 0 {
+            self.buckets = Vec::new();
+        } else if self.buckets.len() > MIN_BUCKETS && self.len * MIN_FILL < self.buckets.len() {
+            self.resize(self.len.next_power_of_two().max(MIN_BUCKETS));
+        }
+        Some(value)
+    }
+
+    /// Moves every entry to a table of `size` buckets, a power of two.
+    fn resize(&mut self, size: usize) {
+        let old = mem::replace(&mut self.buckets, (0..size).map(|_| Vec::new()).collect());
+        for (key, value) in old.into_iter().flatten() {
+            let index = self.bucket_of(&key);
+            self.buckets[index].push((key, value));
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.buckets.iter().flatten().map(|(k, v)| (k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// An entry picked at random, `None` if the table is empty.
+    ///
+    /// A random bucket is drawn until a non-empty one comes up, which takes
+    /// a few draws at most since the table is kept at least 1/`MIN_FILL`
+    /// full, then a random entry of it. Entries sharing their bucket are a
+    /// bit less likely to be picked, as in Redis.
+    pub fn random(&self) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let mask = self.buckets.len() - 1;
+        loop {
+            let bucket = &self.buckets[random_u64() as usize & mask];
+            if !bucket.is_empty() {
+                let (key, value) = &bucket[random_u64() as usize % bucket.len()];
+                return Some((key, value));
+            }
+        }
+    }
+
+    /// Visits the buckets from `cursor` on until at least `count` entries
+    /// were seen, and returns the cursor to continue from, 0 once the whole
+    /// table was walked.
+    ///
+    /// Buckets are visited in the order of their reversed index bits, as in
+    /// Redis' `dictScan`, so that the buckets an entry may be moved to when
+    /// the table is resized are the ones left to visit: every entry present
+    /// for the whole iteration is returned, some possibly more than once if
+    /// the table shrank meanwhile.
+    pub fn scan(&self, mut cursor: u64, count: usize, mut visit: impl FnMut(&K, &V)) -> u64 {
+        if self.buckets.is_empty() {
+            return 0;
+        }
+        let mask = (self.buckets.len() - 1) as u64;
+        let mut seen = 0;
+        // empty buckets count too, so that a sparse table is walked in
+        // bounded steps
+        let mut steps = count.saturating_mul(10);
+        loop {
+            for (key, value) in &self.buckets[(cursor & mask) as usize] {
+                visit(key, value);
+                seen += 1;
+            }
+            cursor |= !mask;
+            cursor = cursor.reverse_bits().wrapping_add(1).reverse_bits();
+            steps = steps.saturating_sub(1);
+            if cursor == 0 || seen >= count || steps == 0 {
+                return cursor;
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq, V: PartialEq> PartialEq for Dict<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for Dict<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.buckets.iter().flatten().map(|(k, v)| (k, v)))
+            .finish()
+    }
+}
+
+impl<K: Hash + Eq, V> FromIterator<(K, V)> for Dict<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut dict = Dict::new();
+        for (key, value) in iter {
+            dict.insert(key, value);
+        }
+        dict
+    }
+}
+
+impl<K, V> IntoIterator for Dict<K, V> {
+    type Item = (K, V);
+    type IntoIter = std::iter::Flatten<std::vec::IntoIter<Vec<(K, V)>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.buckets.into_iter().flatten()
+    }
+}
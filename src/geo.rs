@@ -0,0 +1,332 @@
+//! Geohashes of the geo commands, which store places in sorted sets scored
+//! by the 52 bit geohash of their coordinates, computed the way Redis does
+//! so that scores and search results agree with it.
+
+/// Bits of precision per coordinate.
+pub const STEP_MAX: u32 = 26;
+
+pub const LONG_MIN: f64 = -180.0;
+pub const LONG_MAX: f64 = 180.0;
+/// Latitudes stop where the Web Mercator projection does.
+pub const LAT_MIN: f64 = -85.051_128_78;
+pub const LAT_MAX: f64 = 85.051_128_78;
+
+/// Earth's quadratic mean radius for WGS-84, the one Redis uses.
+const EARTH_RADIUS_IN_METERS: f64 = 6_372_797.560_856;
+const MERCATOR_MAX: f64 = 20_037_726.37;
+
+/// Longitude and latitude, in degrees.
+pub type Point = (f64, f64);
+
+/// A geohash of `step` bits per coordinate, longitude bits in the odd
+/// positions and latitude bits in the even ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeoHash {
+    pub bits: u64,
+    pub step: u32,
+}
+
+/// Spreads the 32 bits of `x` over the even bits of the result.
+fn spread(x: u32) -> u64 {
+    let mut x = x as u64;
+    x = (x | (x << 16)) & 0x0000_ffff_0000_ffff;
+    x = (x | (x << 8)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+/// Gathers the even bits of `x`.
+fn squash(x: u64) -> u32 {
+    let mut x = x & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x >> 4)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x >> 8)) & 0x0000_ffff_0000_ffff;
+    ((x | (x >> 16)) & 0x0000_0000_ffff_ffff) as u32
+}
+
+/// Whether a point can be indexed.
+pub fn is_valid(point: Point) -> bool {
+    (LONG_MIN..=LONG_MAX).contains(&point.0) && (LAT_MIN..=LAT_MAX).contains(&point.1)
+}
+
+impl GeoHash {
+    /// Encodes a point within the given latitude range, which is the
+    /// Mercator one for scores and the full one for GEOHASH strings.
+    fn encode_in(point: Point, step: u32, lat_range: (f64, f64)) -> GeoHash {
+        let scale = (1u64 << step) as f64;
+        let lat = (point.1 - lat_range.0) / (lat_range.1 - lat_range.0) * scale;
+        let long = (point.0 - LONG_MIN) / (LONG_MAX - LONG_MIN) * scale;
+        GeoHash {
+            bits: spread(lat as u32) | (spread(long as u32) << 1),
+            step,
+        }
+    }
+
+    pub fn encode(point: Point, step: u32) -> GeoHash {
+        GeoHash::encode_in(point, step, (LAT_MIN, LAT_MAX))
+    }
+
+    /// The area of the cell, as `(min, max)` longitudes and latitudes.
+    pub fn area(self) -> ((f64, f64), (f64, f64)) {
+        let lat = squash(self.bits) as f64;
+        let long = squash(self.bits >> 1) as f64;
+        let scale = (1u64 << self.step) as f64;
+        let lat_span = LAT_MAX - LAT_MIN;
+        let long_span = LONG_MAX - LONG_MIN;
+        (
+            (
+                LONG_MIN + long / scale * long_span,
+                LONG_MIN + (long + 1.0) / scale * long_span,
+            ),
+            (
+                LAT_MIN + lat / scale * lat_span,
+                LAT_MIN + (lat + 1.0) / scale * lat_span,
+            ),
+        )
+    }
+
+    /// The center of the cell, which is where a place is reported to be.
+    pub fn decode(self) -> Point {
+        let ((long_min, long_max), (lat_min, lat_max)) = self.area();
+        (
+            ((long_min + long_max) / 2.0).clamp(LONG_MIN, LONG_MAX),
+            ((lat_min + lat_max) / 2.0).clamp(LAT_MIN, LAT_MAX),
+        )
+    }
+
+    /// The cell `dx` cells east and `dy` cells north of this one.
+    fn moved(self, dx: i8, dy: i8) -> GeoHash {
+        let shift = 64 - self.step * 2;
+        let mut x = self.bits & 0xaaaa_aaaa_aaaa_aaaa;
+        let mut y = self.bits & 0x5555_5555_5555_5555;
+        if dx != 0 {
+            let zz = 0x5555_5555_5555_5555u64 >> shift;
+            x = if dx > 0 {
+                x.wrapping_add(zz + 1)
+            } else {
+                (x | zz).wrapping_sub(zz + 1)
+            };
+            x &= 0xaaaa_aaaa_aaaa_aaaau64 >> shift;
+        }
+        if dy != 0 {
+            let zz = 0xaaaa_aaaa_aaaa_aaaau64 >> shift;
+            y = if dy > 0 {
+                y.wrapping_add(zz + 1)
+            } else {
+                (y | zz).wrapping_sub(zz + 1)
+            };
+            y &= 0x5555_5555_5555_5555u64 >> shift;
+        }
+        GeoHash {
+            bits: x | y,
+            step: self.step,
+        }
+    }
+
+    /// The range of 52 bit scores of the places within the cell.
+    pub fn score_range(self) -> (f64, f64) {
+        let shift = (STEP_MAX - self.step) * 2;
+        (
+            (self.bits << shift) as f64,
+            ((self.bits + 1) << shift) as f64,
+        )
+    }
+}
+
+/// Score of a place in the sorted set.
+pub fn score(point: Point) -> f64 {
+    GeoHash::encode(point, STEP_MAX).bits as f64
+}
+
+/// The point a score stands for.
+pub fn decode_score(score: f64) -> Point {
+    GeoHash {
+        bits: score as u64,
+        step: STEP_MAX,
+    }
+    .decode()
+}
+
+/// The standard 11 characters geohash of a score, as reported by GEOHASH.
+pub fn hash_string(score: f64) -> Vec<u8> {
+    const ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+    // scores use Mercator latitudes, standard geohashes don't
+    let hash = GeoHash::encode_in(decode_score(score), STEP_MAX, (-90.0, 90.0));
+    (0..11)
+        .map(|i| {
+            // the last character would need bits past the 52 there are
+            let index = if i == 10 {
+                0
+            } else {
+                (hash.bits >> (52 - (i + 1) * 5)) & 0x1f
+            };
+            ALPHABET[index as usize]
+        })
+        .collect()
+}
+
+/// Distance in meters between two points with the haversine formula.
+pub fn distance(from: Point, to: Point) -> f64 {
+    let (lon1, lat1) = (from.0.to_radians(), from.1.to_radians());
+    let (lon2, lat2) = (to.0.to_radians(), to.1.to_radians());
+    let v = ((lon2 - lon1) / 2.0).sin();
+    // points on the same meridian don't need the whole formula
+    if v == 0.0 {
+        return lat_distance(from.1, to.1);
+    }
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let a = u * u + lat1.cos() * lat2.cos() * v * v;
+    2.0 * EARTH_RADIUS_IN_METERS * a.sqrt().asin()
+}
+
+fn lat_distance(lat1: f64, lat2: f64) -> f64 {
+    EARTH_RADIUS_IN_METERS * (lat2.to_radians() - lat1.to_radians()).abs()
+}
+
+/// Parses a distance unit into the number of meters it stands for.
+pub fn parse_unit(arg: &[u8]) -> Option<f64> {
+    match arg.to_ascii_lowercase().as_slice() {
+        b"m" => Some(1.0),
+        b"km" => Some(1000.0),
+        b"ft" => Some(0.3048),
+        b"mi" => Some(1609.34),
+        _ => None,
+    }
+}
+
+pub const INVALID_UNIT: &str = "ERR unsupported unit provided. please use M, KM, FT, MI";
+
+/// An area searched by GEOSEARCH, sizes in meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+impl Shape {
+    /// The distance from `center` to `point` if the point is inside the
+    /// shape drawn around `center`.
+    pub fn distance_if_inside(self, center: Point, point: Point) -> Option<f64> {
+        match self {
+            Shape::Radius(radius) => Some(distance(center, point)).filter(|d| *d <= radius),
+            Shape::Box { width, height } => {
+                if lat_distance(point.1, center.1) > height / 2.0
+                    || distance((point.0, point.1), (center.0, point.1)) > width / 2.0
+                {
+                    return None;
+                }
+                Some(distance(center, point))
+            }
+        }
+    }
+
+    /// Half the height and width of the shape.
+    fn half_sizes(self) -> (f64, f64) {
+        match self {
+            Shape::Radius(radius) => (radius, radius),
+            Shape::Box { width, height } => (height / 2.0, width / 2.0),
+        }
+    }
+
+    /// The longitudes and latitudes bounding the shape around `center`.
+    fn bounds(self, center: Point) -> ((f64, f64), (f64, f64)) {
+        let (height, width) = self.half_sizes();
+        let (long, lat) = center;
+        let lat_delta = (height / EARTH_RADIUS_IN_METERS).to_degrees();
+        let long_delta =
+            |lat: f64| (width / EARTH_RADIUS_IN_METERS / lat.to_radians().cos()).to_degrees();
+        // the shape is widest on the side closest to the pole
+        let long_delta = if lat < 0.0 {
+            long_delta(lat - lat_delta)
+        } else {
+            long_delta(lat + lat_delta)
+        };
+        (
+            (long - long_delta, long + long_delta),
+            (lat - lat_delta, lat + lat_delta),
+        )
+    }
+
+    /// The cells to scan to find every place in the shape around `center`:
+    /// the cell of the center and its neighbours, at a precision where
+    /// that is enough to cover the shape.
+    pub fn cells(self, center: Point) -> Vec<GeoHash> {
+        let (height, width) = self.half_sizes();
+        let radius = match self {
+            Shape::Radius(radius) => radius,
+            Shape::Box { .. } => (width * width + height * height).sqrt(),
+        };
+        let ((min_long, max_long), (min_lat, max_lat)) = self.bounds(center);
+        let mut step = estimate_step(radius, center.1);
+        let mut hash = GeoHash::encode(center, step);
+        // near the edges of the cell the neighbours may not reach far enough
+        let reaches = |hash: GeoHash| {
+            hash.moved(0, 1).area().1.1 >= max_lat
+                && hash.moved(0, -1).area().1.0 <= min_lat
+                && hash.moved(1, 0).area().0.1 >= max_long
+                && hash.moved(-1, 0).area().0.0 <= min_long
+        };
+        if step > 1 && !reaches(hash) {
+            step -= 1;
+            hash = GeoHash::encode(center, step);
+        }
+        let ((long_lo, long_hi), (lat_lo, lat_hi)) = hash.area();
+        // neighbours on the sides the shape doesn't reach are left out
+        let skip_south = step >= 2 && lat_lo < min_lat;
+        let skip_north = step >= 2 && lat_hi > max_lat;
+        let skip_west = step >= 2 && long_lo < min_long;
+        let skip_east = step >= 2 && long_hi > max_long;
+        let neighbours = [
+            (0, 0),
+            (0, 1),
+            (0, -1),
+            (1, 0),
+            (-1, 0),
+            (1, 1),
+            (-1, 1),
+            (1, -1),
+            (-1, -1),
+        ];
+        let mut cells: Vec<GeoHash> = Vec::with_capacity(neighbours.len());
+        for (dx, dy) in neighbours {
+            if (dy > 0 && skip_north)
+                || (dy < 0 && skip_south)
+                || (dx > 0 && skip_east)
+                || (dx < 0 && skip_west)
+            {
+                continue;
+            }
+            let cell = hash.moved(dx, dy);
+            // at the coarsest steps neighbours can wrap around to each other
+            if !cells.contains(&cell) {
+                cells.push(cell);
+            }
+        }
+        cells
+    }
+}
+
+/// The precision at which the cell of a point and its neighbours cover
+/// `radius` meters around it.
+fn estimate_step(mut radius: f64, lat: f64) -> u32 {
+    if radius == 0.0 {
+        return STEP_MAX;
+    }
+    let mut step: i32 = 1;
+    while radius < MERCATOR_MAX {
+        radius *= 2.0;
+        step += 1;
+    }
+    // make sure the range is included in most of the base cases
+    step -= 2;
+    // meridians get closer towards the poles
+    if !(-66.0..=66.0).contains(&lat) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&lat) {
+            step -= 1;
+        }
+    }
+    step.clamp(1, STEP_MAX as i32) as u32
+}
@@ -0,0 +1,96 @@
+/// Matches `string` against a Redis glob `pattern`.
+///
+/// Supports `*` (any run of bytes), `?` (any single byte), `[abc]`, `[^abc]`
+/// and `[a-z]` classes, and `\` to escape the next byte, with the same
+/// quirks as Redis' `stringmatchlen`: an unterminated class ends with the
+/// pattern, reversed ranges like `[z-a]` are accepted and a `-` followed by
+/// the closing bracket still makes a range.
+pub fn glob_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // position right after the last star seen and the string offset it was
+    // tried at, to backtrack to when a later token fails to match
+    let mut backtrack: Option<(usize, usize)> = None;
+    loop {
+        if p < pattern.len() && pattern[p] == b'*' {
+            while p < pattern.len() && pattern[p] == b'*' {
+                p += 1;
+            }
+            if p == pattern.len() {
+                return true;
+            }
+            backtrack = Some((p, s));
+            continue;
+        }
+        if s == string.len() {
+            return p == pattern.len();
+        }
+        if p < pattern.len() {
+            let (matched, next) = match_token(pattern, p, string[s], nocase);
+            if matched {
+                p = next;
+                s += 1;
+                continue;
+            }
+        }
+        match backtrack {
+            Some((star_p, star_s)) => {
+                backtrack = Some((star_p, star_s + 1));
+                p = star_p;
+                s = star_s + 1;
+            }
+            None => return false,
+        }
+    }
+}
+
+fn eq(a: u8, b: u8, nocase: bool) -> bool {
+    if nocase {
+        a.eq_ignore_ascii_case(&b)
+    } else {
+        a == b
+    }
+}
+
+/// Matches the single byte `c` against the pattern token starting at `p`,
+/// returning whether it matched and where the next token starts.
+fn match_token(pattern: &[u8], p: usize, c: u8, nocase: bool) -> (bool, usize) {
+    match pattern[p] {
+        b'?' => (true, p + 1),
+        b'\\' if p + 1 < pattern.len() => (eq(pattern[p + 1], c, nocase), p + 2),
+        b'[' => {
+            let mut i = p + 1;
+            let negate = pattern.get(i) == Some(&b'^');
+            if negate {
+                i += 1;
+            }
+            let mut matched = false;
+            while i < pattern.len() && pattern[i] != b']' {
+                if pattern[i] == b'\\' && i + 1 < pattern.len() {
+                    // escapes inside a class ignore NOCASE in Redis too
+                    matched |= pattern[i + 1] == c;
+                    i += 2;
+                } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' {
+                    // a range even when it ends on `]`, as in Redis: `[a-]`
+                    // is everything between `]` and `a`, with no class end
+                    let (mut start, mut end, mut c) = (pattern[i], pattern[i + 2], c);
+                    if start > end {
+                        std::mem::swap(&mut start, &mut end);
+                    }
+                    if nocase {
+                        start = start.to_ascii_lowercase();
+                        end = end.to_ascii_lowercase();
+                        c = c.to_ascii_lowercase();
+                    }
+                    matched |= (start..=end).contains(&c);
+                    i += 3;
+                } else {
+                    matched |= eq(pattern[i], c, nocase);
+                    i += 1;
+                }
+            }
+            // skip the closing bracket, if there is one
+            (matched != negate, (i + 1).min(pattern.len()))
+        }
+        other => (eq(other, c, nocase), p + 1),
+    }
+}
@@ -0,0 +1,324 @@
+use std::{borrow::Cow, io::Result};
+
+use crate::{
+    Data, NOT_AN_INTEGER, NullKind, SYNTAX_ERROR, Value,
+    bitmap::{
+        BitOp, FieldType, INVALID_OFFSET, MAX_BIT_OFFSET, Overflow, clamp_range, count, get_bit,
+        parse_offset, position, set_bit,
+    },
+    client::Client,
+    object::{Object, WRONGTYPE},
+    send_error,
+    util::parse_int,
+};
+
+/// Runs `reply` on the string stored at `key`, a missing key reading as an
+/// empty string.
+fn with_string(
+    data: Data,
+    key: &[u8],
+    client: &mut Client,
+    reply: impl FnOnce(&[u8]) -> Value,
+) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    match lock.get_string(key) {
+        Ok(Some(bytes)) => client.send(&reply(&bytes)),
+        Ok(None) => client.send(&reply(&[])),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+/// `SETBIT key offset value`
+pub fn setbit(
+    data: Data,
+    key: &[u8],
+    offset: &[u8],
+    value: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    let Some(offset) = parse_offset(offset) else {
+        return send_error(client, INVALID_OFFSET);
+    };
+    let bit = match value {
+        b"0" => false,
+        b"1" => true,
+        _ => return send_error(client, "ERR bit is not an integer or out of range"),
+    };
+    let mut lock = data.lock().unwrap();
+    match lock.get_or_create_string(key) {
+        Ok(bytes) => client.send(&Value::Integer(set_bit(bytes, offset, bit) as i64)),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+/// `GETBIT key offset`
+pub fn getbit(data: Data, key: &[u8], offset: &[u8], client: &mut Client) -> Result<()> {
+    let Some(offset) = parse_offset(offset) else {
+        return send_error(client, INVALID_OFFSET);
+    };
+    with_string(data, key, client, |bytes| {
+        Value::Integer(get_bit(bytes, offset) as i64)
+    })
+}
+
+/// Whether the indexes of a range are bits rather than bytes.
+fn parse_unit(unit: Option<&Vec<u8>>) -> Option<bool> {
+    match unit.map(|u| u.to_ascii_lowercase()).as_deref() {
+        None | Some(b"byte") => Some(false),
+        Some(b"bit") => Some(true),
+        _ => None,
+    }
+}
+
+/// Turns a range of byte or bit indexes into an inclusive range of bit
+/// offsets into a string of `len` bytes.
+fn bit_range(start: i64, end: i64, bits: bool, len: usize) -> Option<(u64, u64)> {
+    if bits {
+        clamp_range(start, end, len as u64 * 8)
+    } else {
+        clamp_range(start, end, len as u64).map(|(start, end)| (start * 8, end * 8 + 7))
+    }
+}
+
+/// `BITCOUNT key [start end [BYTE|BIT]]`
+pub fn bitcount(data: Data, key: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let range = match args {
+        [] => None,
+        [start, end, unit @ ..] if unit.len() <= 1 => {
+            let (Some(start), Some(end)) = (parse_int(start), parse_int(end)) else {
+                return send_error(client, NOT_AN_INTEGER);
+            };
+            let Some(bits) = parse_unit(unit.first()) else {
+                return send_error(client, SYNTAX_ERROR);
+            };
+            Some((start, end, bits))
+        }
+        _ => return send_error(client, SYNTAX_ERROR),
+    };
+    with_string(data, key, client, |bytes| {
+        let (start, end, bits) = range.unwrap_or((0, -1, false));
+        let total = bit_range(start, end, bits, bytes.len())
+            .map_or(0, |(start, end)| count(bytes, start, end));
+        Value::Integer(total as i64)
+    })
+}
+
+/// `BITPOS key bit [start [end [BYTE|BIT]]]`
+pub fn bitpos(
+    data: Data,
+    key: &[u8],
+    bit: &[u8],
+    args: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let bit = match bit {
+        b"0" => false,
+        b"1" => true,
+        _ => return send_error(client, "ERR The bit argument must be 1 or 0."),
+    };
+    if args.len() > 3 {
+        return send_error(client, SYNTAX_ERROR);
+    }
+    let Some(bounds) = args[..args.len().min(2)]
+        .iter()
+        .map(|arg| parse_int(arg))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return send_error(client, NOT_AN_INTEGER);
+    };
+    let Some(bits) = parse_unit(args.get(2)) else {
+        return send_error(client, SYNTAX_ERROR);
+    };
+    let start = bounds.first().copied().unwrap_or(0);
+    let end = bounds.get(1).copied();
+    with_string(data, key, client, |bytes| {
+        if bytes.is_empty() {
+            return Value::Integer(if bit { -1 } else { 0 });
+        }
+        let Some((first, last)) = bit_range(start, end.unwrap_or(-1), bits, bytes.len()) else {
+            return Value::Integer(-1);
+        };
+        match position(bytes, bit, first, last) {
+            Some(offset) => Value::Integer(offset as i64),
+            // without an explicit end the string is seen as padded with
+            // zeros, so the first clear bit is right after the range
+            None if !bit && end.is_none() => Value::Integer(last as i64 + 1),
+            None => Value::Integer(-1),
+        }
+    })
+}
+
+/// `BITOP AND|OR|XOR|NOT destkey key [key ...]`
+pub fn bitop(
+    data: Data,
+    operation: &[u8],
+    destkey: &[u8],
+    keys: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let Some(op) = BitOp::parse(operation) else {
+        return send_error(client, SYNTAX_ERROR);
+    };
+    if op == BitOp::Not && keys.len() != 1 {
+        return send_error(
+            client,
+            "ERR BITOP NOT must be called with a single source key.",
+        );
+    }
+    let mut lock = data.lock().unwrap();
+    let mut sources = Vec::with_capacity(keys.len());
+    for key in keys {
+        match lock.get_string(key) {
+            Ok(bytes) => sources.push(bytes.map(Cow::into_owned).unwrap_or_default()),
+            Err(_) => return send_error(client, WRONGTYPE),
+        }
+    }
+    let sources: Vec<&[u8]> = sources.iter().map(Vec::as_slice).collect();
+    let result = op.apply(&sources);
+    let len = result.len();
+    if result.is_empty() {
+        lock.remove(destkey);
+    } else {
+        lock.insert(destkey.to_vec(), Object::String(result));
+    }
+    client.send(&Value::Integer(len as i64))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FieldOpKind {
+    Get,
+    Set(i64),
+    IncrBy(i64),
+}
+
+/// A BITFIELD subcommand, with the overflow behaviour in effect for it.
+#[derive(Debug, Clone, Copy)]
+struct FieldOp {
+    kind: FieldOpKind,
+    ty: FieldType,
+    offset: u64,
+    overflow: Overflow,
+}
+
+/// Parses a BITFIELD offset: a bit offset, or `#n` for the n-th integer of
+/// the given type.
+fn parse_field_offset(arg: &[u8], ty: FieldType) -> Option<u64> {
+    let offset = match arg {
+        [b'#', n @ ..] => parse_offset(n)?.checked_mul(ty.bits as u64)?,
+        offset => parse_offset(offset)?,
+    };
+    (offset + ty.bits as u64 - 1 <= MAX_BIT_OFFSET).then_some(offset)
+}
+
+fn parse_field_ops(
+    args: &[Vec<u8>],
+    read_only: bool,
+) -> std::result::Result<Vec<FieldOp>, &'static str> {
+    let mut ops = Vec::new();
+    let mut overflow = Overflow::default();
+    let mut i = 0;
+    while i < args.len() {
+        let name = args[i].to_ascii_lowercase();
+        if name == b"overflow" {
+            let arg = args.get(i + 1).ok_or(SYNTAX_ERROR)?;
+            overflow = Overflow::parse(arg).ok_or("ERR Invalid OVERFLOW type specified")?;
+            i += 2;
+            continue;
+        }
+        let arity = match name.as_slice() {
+            b"get" => 3,
+            b"set" | b"incrby" => 4,
+            _ => return Err(SYNTAX_ERROR),
+        };
+        if read_only && name != b"get" {
+            return Err("ERR BITFIELD_RO only supports the GET subcommand");
+        }
+        let Some(op) = args.get(i + 1..i + arity) else {
+            return Err(SYNTAX_ERROR);
+        };
+        let ty = FieldType::parse(&op[0]).ok_or(
+            "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not \
+             supported but i64 is.",
+        )?;
+        let offset = parse_field_offset(&op[1], ty).ok_or(INVALID_OFFSET)?;
+        let kind = match name.as_slice() {
+            b"get" => FieldOpKind::Get,
+            b"set" => FieldOpKind::Set(parse_int(&op[2]).ok_or(NOT_AN_INTEGER)?),
+            _ => FieldOpKind::IncrBy(parse_int(&op[2]).ok_or(NOT_AN_INTEGER)?),
+        };
+        ops.push(FieldOp {
+            kind,
+            ty,
+            offset,
+            overflow,
+        });
+        i += arity;
+    }
+    Ok(ops)
+}
+
+fn bitfield_command(
+    data: Data,
+    key: &[u8],
+    args: &[Vec<u8>],
+    read_only: bool,
+    client: &mut Client,
+) -> Result<()> {
+    let ops = match parse_field_ops(args, read_only) {
+        Ok(ops) => ops,
+        Err(err) => return send_error(client, err),
+    };
+    let writes = ops.iter().filter(|op| !matches!(op.kind, FieldOpKind::Get));
+    // the string is grown up front to hold every field written, like Redis
+    let Some(end) = writes.map(|op| op.offset + op.ty.bits as u64).max() else {
+        return with_string(data, key, client, |bytes| {
+            let values = ops
+                .iter()
+                .map(|op| Value::Integer(op.ty.read(bytes, op.offset)))
+                .collect();
+            Value::Array(values)
+        });
+    };
+    let mut lock = data.lock().unwrap();
+    let bytes = match lock.get_or_create_string(key) {
+        Ok(bytes) => bytes,
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let len = end.div_ceil(8) as usize;
+    if bytes.len() < len {
+        bytes.resize(len, 0);
+    }
+    let mut replies = Vec::with_capacity(ops.len());
+    for op in ops {
+        let old = op.ty.read(bytes, op.offset);
+        let new = match op.kind {
+            FieldOpKind::Get => {
+                replies.push(Value::Integer(old));
+                continue;
+            }
+            FieldOpKind::Set(value) => op.ty.fit(value as i128, op.overflow),
+            FieldOpKind::IncrBy(incr) => op.ty.fit(old as i128 + incr as i128, op.overflow),
+        };
+        let Some(new) = new else {
+            replies.push(Value::Null(NullKind::BulkString));
+            continue;
+        };
+        op.ty.write(bytes, op.offset, new);
+        replies.push(Value::Integer(match op.kind {
+            FieldOpKind::Set(_) => old,
+            _ => new,
+        }));
+    }
+    client.send(&Value::Array(replies))
+}
+
+/// `BITFIELD key [GET encoding offset | [OVERFLOW WRAP|SAT|FAIL]
+/// SET encoding offset value | INCRBY encoding offset increment ...]`
+pub fn bitfield(data: Data, key: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    bitfield_command(data, key, args, false, client)
+}
+
+/// `BITFIELD_RO key [GET encoding offset ...]`
+pub fn bitfield_ro(data: Data, key: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    bitfield_command(data, key, args, true, client)
+}
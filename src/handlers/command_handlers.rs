@@ -1,77 +1,235 @@
-use std::io::{Result, Write};
+use std::{borrow::Cow, io::Result};
 
-use crate::{Data, Value, send_error};
+use crate::{
+    Data, NOT_A_FLOAT, NOT_AN_INTEGER, NullKind, SYNTAX_ERROR, Value,
+    client::Client,
+    db::now_ms,
+    object::{Object, WRONGTYPE},
+    send_error,
+    util::{format_human_float, parse_float, parse_int},
+};
 
-pub fn get(data: Data, key: &str, stream: &mut dyn Write) -> Result<()> {
-    let lock = data.lock().unwrap();
-    let value = lock.get(key);
-    if let Some(v) = value {
-        stream.write_all(&v.to_bytes())?;
-        stream.flush()?;
-    } else {
-        let _ = stream.write_all(&Value::Null(()).to_bytes());
-        stream.flush()?;
+/// What SET does with the expire of the key it writes.
+enum SetExpire {
+    /// Drop it, the default.
+    Clear,
+    /// Expire at the given unix time in milliseconds.
+    At(u64),
+    /// Leave it as it is.
+    Keep,
+}
+
+/// Optional arguments of SET.
+struct SetOptions {
+    expire: SetExpire,
+    /// Only set the key if it does not exist.
+    nx: bool,
+    /// Only set the key if it already exists.
+    xx: bool,
+    /// Reply with the previous value instead of OK.
+    get: bool,
+}
+
+/// Parses the optional arguments of SET, replying with the error and
+/// returning `None` if they are invalid.
+fn parse_set_options(options: &[Vec<u8>], client: &mut Client) -> Result<Option<SetOptions>> {
+    let mut ret = SetOptions {
+        expire: SetExpire::Clear,
+        nx: false,
+        xx: false,
+        get: false,
+    };
+    let mut options = options.iter();
+    while let Some(opt) = options.next() {
+        let opt = opt.to_ascii_lowercase();
+        let unit = match opt.as_slice() {
+            b"nx" if !ret.xx => {
+                ret.nx = true;
+                continue;
+            }
+            b"xx" if !ret.nx => {
+                ret.xx = true;
+                continue;
+            }
+            b"get" => {
+                ret.get = true;
+                continue;
+            }
+            b"keepttl" if matches!(ret.expire, SetExpire::Clear) => {
+                ret.expire = SetExpire::Keep;
+                continue;
+            }
+            b"ex" | b"exat" if matches!(ret.expire, SetExpire::Clear) => 1000,
+            b"px" | b"pxat" if matches!(ret.expire, SetExpire::Clear) => 1,
+            _ => {
+                send_error(client, SYNTAX_ERROR)?;
+                return Ok(None);
+            }
+        };
+        let Some(when) = options.next() else {
+            send_error(client, SYNTAX_ERROR)?;
+            return Ok(None);
+        };
+        let Some(when) = parse_int(when) else {
+            send_error(client, NOT_AN_INTEGER)?;
+            return Ok(None);
+        };
+        match expire_time(&opt, unit, when) {
+            Some(at) => ret.expire = SetExpire::At(at),
+            None => {
+                send_error(client, "ERR invalid expire time in 'set' command")?;
+                return Ok(None);
+            }
+        }
     }
-    Ok(())
+    Ok(Some(ret))
 }
-pub fn set(data: Data, key: &str, value: Value, stream: &mut dyn Write) -> Result<()> {
+
+/// Unix time in milliseconds at which the `EX`, `PX`, `EXAT` or `PXAT`
+/// option `opt` expires, `when` being in units of `unit` milliseconds.
+/// `None` if `when` isn't positive or overflows.
+fn expire_time(opt: &[u8], unit: i64, when: i64) -> Option<u64> {
+    if when <= 0 {
+        return None;
+    }
+    let ms = when.checked_mul(unit)?;
+    let at = if opt.ends_with(b"at") {
+        ms
+    } else {
+        ms.checked_add(now_ms() as i64)?
+    };
+    Some(at as u64)
+}
+
+pub fn get(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
     let mut lock = data.lock().unwrap();
-    lock.insert(key.to_string(), value);
-    let resp = Value::String("OK".to_string()).to_bytes();
-    stream.write_all(&resp)?;
-    stream.flush()?;
-    Ok(())
+    match lock.get_string(key) {
+        Ok(Some(v)) => client.send(&Value::BulkString(v.into_owned())),
+        Ok(None) => client.send(&Value::Null(NullKind::BulkString)),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
 }
-pub fn incr(data: Data, key: &str, stream: &mut dyn Write) -> Result<()> {
+pub fn set(
+    data: Data,
+    key: &[u8],
+    value: &[u8],
+    options: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let Some(options) = parse_set_options(options, client)? else {
+        return Ok(());
+    };
     let mut lock = data.lock().unwrap();
-    if let Some(v) = lock.get(key) {
-        let v = v.to_string().parse::<i64>();
-        if let Ok(v) = v {
-            let v = Value::Integer(v + 1);
-            stream.write_all(&v.to_bytes())?;
-            lock.insert(key.to_string(), Value::BulkString(v.to_string()));
-            stream.flush()?;
-        } else {
-            send_error(stream, "ERR value is not an integer or out of range")?;
+    let old = if options.get {
+        match lock.get_string(key) {
+            Ok(old) => old.map(Cow::into_owned),
+            Err(_) => return send_error(client, WRONGTYPE),
         }
     } else {
-        let v = Value::Integer(1);
-        stream.write_all(&v.to_bytes())?;
-        lock.insert(key.to_string(), Value::BulkString(v.to_string()));
-        stream.flush()?;
+        None
+    };
+    let old = old.map_or(Value::Null(NullKind::BulkString), Value::BulkString);
+    let exists = lock.contains_key(key);
+    if (options.nx && exists) || (options.xx && !exists) {
+        if options.get {
+            return client.send(&old);
+        }
+        return client.send(&Value::Null(NullKind::BulkString));
+    }
+    let value = Object::String(value.to_vec());
+    match options.expire {
+        SetExpire::Clear => lock.insert(key.to_vec(), value),
+        SetExpire::Keep => lock.insert_keep_ttl(key.to_vec(), value),
+        SetExpire::At(at) => {
+            lock.insert(key.to_vec(), value);
+            lock.set_expire(key, at);
+        }
+    }
+    if options.get {
+        client.send(&old)
+    } else {
+        client.send(&Value::String("OK".to_string()))
     }
-    Ok(())
 }
 
-pub fn decr(data: Data, key: &str, stream: &mut dyn Write) -> Result<()> {
+/// Adds `delta` to the integer stored at `key`, a missing key counting as
+/// zero, and keeps the result integer-encoded.
+fn incr_by_generic(data: Data, key: &[u8], delta: i64, client: &mut Client) -> Result<()> {
     let mut lock = data.lock().unwrap();
-    let value = lock.get(key);
-    if let Some(v) = value {
-        let v = v.to_string().parse::<i64>();
-        if let Ok(v) = v {
-            let v = Value::Integer(v - 1);
-            stream.write_all(&v.to_bytes())?;
-            lock.insert(key.to_string(), Value::BulkString(v.to_string()));
-            stream.flush()?;
-        } else {
-            let resp =
-                Value::Error("ERR value is not an integer or out of range".to_string()).to_bytes();
-            stream.write_all(&resp)?;
-            stream.flush()?;
-        }
-    } else {
-        let v = Value::Integer(-1);
-        stream.write_all(&v.to_bytes())?;
-        lock.insert(key.to_string(), Value::BulkString(v.to_string()));
-        stream.flush()?;
+    let current = match lock.get(key) {
+        None => 0,
+        Some(Object::Int(n)) => *n,
+        Some(Object::String(bytes)) => match parse_int(bytes) {
+            Some(n) => n,
+            None => return send_error(client, NOT_AN_INTEGER),
+        },
+        Some(_) => return send_error(client, WRONGTYPE),
+    };
+    let Some(value) = current.checked_add(delta) else {
+        return send_error(client, "ERR increment or decrement would overflow");
+    };
+    match lock.get_mut(key) {
+        Some(Object::Int(n)) => *n = value,
+        _ => lock.insert_keep_ttl(key.to_vec(), Object::Int(value)),
+    }
+    client.send(&Value::Integer(value))
+}
+
+pub fn incr(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    incr_by_generic(data, key, 1, client)
+}
+
+pub fn decr(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    incr_by_generic(data, key, -1, client)
+}
+
+/// `INCRBY key increment`
+pub fn incrby(data: Data, key: &[u8], increment: &[u8], client: &mut Client) -> Result<()> {
+    let Some(increment) = parse_int(increment) else {
+        return send_error(client, NOT_AN_INTEGER);
+    };
+    incr_by_generic(data, key, increment, client)
+}
+
+/// `DECRBY key decrement`
+pub fn decrby(data: Data, key: &[u8], decrement: &[u8], client: &mut Client) -> Result<()> {
+    let Some(decrement) = parse_int(decrement) else {
+        return send_error(client, NOT_AN_INTEGER);
+    };
+    let Some(delta) = decrement.checked_neg() else {
+        return send_error(client, "ERR decrement would overflow");
+    };
+    incr_by_generic(data, key, delta, client)
+}
+
+/// `INCRBYFLOAT key increment`
+pub fn incrbyfloat(data: Data, key: &[u8], increment: &[u8], client: &mut Client) -> Result<()> {
+    let Some(increment) = parse_float(increment) else {
+        return send_error(client, NOT_A_FLOAT);
+    };
+    let mut lock = data.lock().unwrap();
+    let current = match lock.get_string(key) {
+        Ok(None) => 0.0,
+        Ok(Some(bytes)) => match parse_float(&bytes) {
+            Some(current) => current,
+            None => return send_error(client, NOT_A_FLOAT),
+        },
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let value = current + increment;
+    if !value.is_finite() {
+        return send_error(client, "ERR increment would produce NaN or Infinity");
     }
-    Ok(())
+    // the result is kept as text, the way Redis formats it
+    let bytes = format_human_float(value).into_bytes();
+    lock.insert_keep_ttl(key.to_vec(), Object::String(bytes.clone()));
+    client.send(&Value::BulkString(bytes))
 }
 
 pub fn del(
     data: Data,
-    keys: &mut impl Iterator<Item = String>,
-    stream: &mut dyn Write,
+    keys: &mut impl Iterator<Item = Vec<u8>>,
+    client: &mut Client,
 ) -> Result<()> {
     let mut count = 0;
     let mut lock = data.lock().unwrap();
@@ -80,5 +238,382 @@ pub fn del(
             count += 1;
         }
     }
-    stream.write_all(&Value::Integer(count).to_bytes())
+    client.send(&Value::Integer(count))
+}
+
+/// Redis caps string values at 512MB.
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+const STRING_TOO_LONG: &str = "ERR string exceeds maximum allowed size (proto-max-bulk-len)";
+
+/// `APPEND key value`
+pub fn append(data: Data, key: &[u8], value: &[u8], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let Ok(bytes) = lock.get_or_create_string(key) else {
+        return send_error(client, WRONGTYPE);
+    };
+    if bytes.len() + value.len() > MAX_STRING_LEN {
+        return send_error(client, STRING_TOO_LONG);
+    }
+    bytes.extend_from_slice(value);
+    client.send(&Value::Integer(bytes.len() as i64))
+}
+
+/// `STRLEN key`
+pub fn strlen(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    match lock.get_string(key) {
+        Ok(bytes) => client.send(&Value::Integer(bytes.map_or(0, |bytes| bytes.len()) as i64)),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+/// `GETRANGE key start end`
+pub fn getrange(
+    data: Data,
+    key: &[u8],
+    start: &[u8],
+    end: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    let (Some(start), Some(end)) = (parse_int(start), parse_int(end)) else {
+        return send_error(client, NOT_AN_INTEGER);
+    };
+    let mut lock = data.lock().unwrap();
+    let bytes = match lock.get_string(key) {
+        Ok(bytes) => bytes.unwrap_or_default(),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let len = bytes.len() as i64;
+    if start < 0 && end < 0 && start > end {
+        return client.send(&Value::BulkString(Vec::new()));
+    }
+    let start = if start < 0 { start + len } else { start }.max(0);
+    let end = if end < 0 { end + len } else { end }.max(0).min(len - 1);
+    if len == 0 || start > end {
+        return client.send(&Value::BulkString(Vec::new()));
+    }
+    client.send(&Value::BulkString(
+        bytes[start as usize..=end as usize].to_vec(),
+    ))
+}
+
+/// `SETRANGE key offset value`
+pub fn setrange(
+    data: Data,
+    key: &[u8],
+    offset: &[u8],
+    value: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    let Some(offset) = parse_int(offset) else {
+        return send_error(client, NOT_AN_INTEGER);
+    };
+    if offset < 0 {
+        return send_error(client, "ERR offset is out of range");
+    }
+    let offset = offset as usize;
+    let mut lock = data.lock().unwrap();
+    let len = match lock.get_string(key) {
+        Ok(bytes) => bytes.map(|bytes| bytes.len()),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    // an empty value changes nothing, not even creating the key
+    if value.is_empty() {
+        return client.send(&Value::Integer(len.unwrap_or(0) as i64));
+    }
+    if offset.saturating_add(value.len()) > MAX_STRING_LEN {
+        return send_error(client, STRING_TOO_LONG);
+    }
+    let Ok(bytes) = lock.get_or_create_string(key) else {
+        return send_error(client, WRONGTYPE);
+    };
+    let end = offset + value.len();
+    if bytes.len() < end {
+        bytes.resize(end, 0);
+    }
+    bytes[offset..end].copy_from_slice(value);
+    client.send(&Value::Integer(bytes.len() as i64))
+}
+
+/// `GETDEL key`
+pub fn getdel(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    match lock.get_string(key) {
+        Ok(Some(bytes)) => {
+            let bytes = bytes.into_owned();
+            lock.remove(key);
+            client.send(&Value::BulkString(bytes))
+        }
+        Ok(None) => client.send(&Value::Null(NullKind::BulkString)),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+/// Parses the optional arguments of GETEX, `Keep` standing for no option.
+fn parse_getex_options(options: &[Vec<u8>]) -> std::result::Result<SetExpire, &'static str> {
+    let mut expire = SetExpire::Keep;
+    let mut options = options.iter();
+    while let Some(opt) = options.next() {
+        if !matches!(expire, SetExpire::Keep) {
+            return Err(SYNTAX_ERROR);
+        }
+        let opt = opt.to_ascii_lowercase();
+        let unit = match opt.as_slice() {
+            b"persist" => {
+                expire = SetExpire::Clear;
+                continue;
+            }
+            b"ex" | b"exat" => 1000,
+            b"px" | b"pxat" => 1,
+            _ => return Err(SYNTAX_ERROR),
+        };
+        let when = options.next().ok_or(SYNTAX_ERROR)?;
+        let when = parse_int(when).ok_or(NOT_AN_INTEGER)?;
+        let at =
+            expire_time(&opt, unit, when).ok_or("ERR invalid expire time in 'getex' command")?;
+        expire = SetExpire::At(at);
+    }
+    Ok(expire)
+}
+
+/// `GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | PERSIST]`
+pub fn getex(data: Data, key: &[u8], options: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let expire = match parse_getex_options(options) {
+        Ok(expire) => expire,
+        Err(err) => return send_error(client, err),
+    };
+    let mut lock = data.lock().unwrap();
+    let bytes = match lock.get_string(key) {
+        Ok(Some(bytes)) => bytes.into_owned(),
+        Ok(None) => return client.send(&Value::Null(NullKind::BulkString)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    match expire {
+        SetExpire::Keep => {}
+        SetExpire::Clear => {
+            lock.persist(key);
+        }
+        SetExpire::At(at) => {
+            lock.set_expire(key, at);
+        }
+    }
+    client.send(&Value::BulkString(bytes))
+}
+
+/// `GETSET key value`
+pub fn getset(data: Data, key: &[u8], value: &[u8], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let old = match lock.get_string(key) {
+        Ok(old) => old.map(Cow::into_owned),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    lock.insert(key.to_vec(), Object::String(value.to_vec()));
+    client.send(&old.map_or(Value::Null(NullKind::BulkString), Value::BulkString))
+}
+
+/// `MGET key [key ...]`
+pub fn mget(data: Data, keys: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    if keys.is_empty() {
+        return send_error(client, "ERR wrong number of arguments for 'mget' command");
+    }
+    let mut lock = data.lock().unwrap();
+    let values = keys
+        .iter()
+        .map(|key| match lock.get_string(key) {
+            Ok(Some(bytes)) => Value::BulkString(bytes.into_owned()),
+            // keys of other types read as missing rather than failing the batch
+            _ => Value::Null(NullKind::BulkString),
+        })
+        .collect();
+    client.send(&Value::Array(values))
+}
+
+/// `MSET key value [key value ...]`
+pub fn mset(data: Data, pairs: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return send_error(client, "ERR wrong number of arguments for 'mset' command");
+    }
+    let mut lock = data.lock().unwrap();
+    for pair in pairs.chunks(2) {
+        lock.insert(pair[0].clone(), Object::String(pair[1].clone()));
+    }
+    client.send(&Value::String("OK".to_string()))
+}
+
+/// `MSETNX key value [key value ...]`
+pub fn msetnx(data: Data, pairs: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return send_error(client, "ERR wrong number of arguments for 'msetnx' command");
+    }
+    let mut lock = data.lock().unwrap();
+    if pairs.chunks(2).any(|pair| lock.contains_key(&pair[0])) {
+        return client.send(&Value::Integer(0));
+    }
+    for pair in pairs.chunks(2) {
+        lock.insert(pair[0].clone(), Object::String(pair[1].clone()));
+    }
+    client.send(&Value::Integer(1))
+}
+
+/// A run of bytes common to both strings of an LCS, as inclusive ranges of
+/// indexes into the first and the second string.
+struct LcsMatch {
+    a: (usize, usize),
+    b: (usize, usize),
+}
+
+impl LcsMatch {
+    fn len(&self) -> usize {
+        self.a.1 - self.a.0 + 1
+    }
+}
+
+/// Longest common subsequence of `a` and `b`, with the runs it is made of
+/// from the last to the first, the order Redis reports them in.
+fn longest_common_subsequence(a: &[u8], b: &[u8]) -> (Vec<u8>, Vec<LcsMatch>) {
+    let width = b.len() + 1;
+    let mut table = vec![0u32; (a.len() + 1) * width];
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            table[i * width + j] = if a[i - 1] == b[j - 1] {
+                table[(i - 1) * width + j - 1] + 1
+            } else {
+                table[(i - 1) * width + j].max(table[i * width + j - 1])
+            };
+        }
+    }
+    let mut sequence = vec![0; table[a.len() * width + b.len()] as usize];
+    let mut matches = Vec::new();
+    let mut current: Option<LcsMatch> = None;
+    let (mut i, mut j, mut k) = (a.len(), b.len(), sequence.len());
+    // walks the table back from the end, growing the current run backwards
+    // for as long as the matches are contiguous in both strings
+    while i > 0 && j > 0 {
+        let mut emit = false;
+        if a[i - 1] == b[j - 1] {
+            sequence[k - 1] = a[i - 1];
+            match &mut current {
+                None => {
+                    current = Some(LcsMatch {
+                        a: (i - 1, i - 1),
+                        b: (j - 1, j - 1),
+                    })
+                }
+                Some(run) if run.a.0 == i && run.b.0 == j => {
+                    run.a.0 -= 1;
+                    run.b.0 -= 1;
+                }
+                Some(_) => emit = true,
+            }
+            if let Some(run) = &current {
+                emit |= run.a.0 == 0 || run.b.0 == 0;
+            }
+            i -= 1;
+            j -= 1;
+            k -= 1;
+        } else {
+            if table[(i - 1) * width + j] > table[i * width + j - 1] {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+            emit = current.is_some();
+        }
+        if emit && let Some(run) = current.take() {
+            matches.push(run);
+        }
+    }
+    (sequence, matches)
+}
+
+/// `LCS key1 key2 [LEN] [IDX] [MINMATCHLEN min-match-len] [WITHMATCHLEN]`
+pub fn lcs(
+    data: Data,
+    key1: &[u8],
+    key2: &[u8],
+    options: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let (mut len, mut idx, mut withmatchlen, mut minmatchlen) = (false, false, false, 0);
+    let mut options = options.iter();
+    while let Some(opt) = options.next() {
+        match opt.to_ascii_lowercase().as_slice() {
+            b"len" => len = true,
+            b"idx" => idx = true,
+            b"withmatchlen" => withmatchlen = true,
+            b"minmatchlen" => {
+                let Some(min) = options.next() else {
+                    return send_error(client, SYNTAX_ERROR);
+                };
+                let Some(min) = parse_int(min) else {
+                    return send_error(client, NOT_AN_INTEGER);
+                };
+                minmatchlen = min.max(0) as usize;
+            }
+            _ => return send_error(client, SYNTAX_ERROR),
+        }
+    }
+    if len && idx {
+        return send_error(
+            client,
+            "ERR If you want both the length and indexes, please just use IDX.",
+        );
+    }
+    let mut lock = data.lock().unwrap();
+    let mut strings = Vec::with_capacity(2);
+    for key in [key1, key2] {
+        match lock.get_string(key) {
+            Ok(bytes) => strings.push(bytes.map(Cow::into_owned).unwrap_or_default()),
+            Err(_) => {
+                return send_error(client, "ERR The specified keys must contain string values");
+            }
+        }
+    }
+    drop(lock);
+    // the table of the dynamic programming is held to the size of a string
+    let table_size = (strings[0].len() + 1)
+        .checked_mul(strings[1].len() + 1)
+        .and_then(|cells| cells.checked_mul(size_of::<u32>()));
+    if table_size.is_none_or(|size| size > MAX_STRING_LEN) {
+        return send_error(
+            client,
+            "ERR Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len",
+        );
+    }
+    let (sequence, matches) = longest_common_subsequence(&strings[0], &strings[1]);
+    if len {
+        return client.send(&Value::Integer(sequence.len() as i64));
+    }
+    if !idx {
+        return client.send(&Value::BulkString(sequence));
+    }
+    let range = |(start, end): (usize, usize)| {
+        Value::Array(vec![
+            Value::Integer(start as i64),
+            Value::Integer(end as i64),
+        ])
+    };
+    let matches = matches
+        .into_iter()
+        .filter(|run| run.len() >= minmatchlen)
+        .map(|run| {
+            let mut reply = vec![range(run.a), range(run.b)];
+            if withmatchlen {
+                reply.push(Value::Integer(run.len() as i64));
+            }
+            Value::Array(reply)
+        })
+        .collect();
+    client.send(&Value::Map(vec![
+        (
+            Value::BulkString(b"matches".to_vec()),
+            Value::Array(matches),
+        ),
+        (
+            Value::BulkString(b"len".to_vec()),
+            Value::Integer(sequence.len() as i64),
+        ),
+    ]))
 }
@@ -0,0 +1,92 @@
+use std::io::Result;
+
+use crate::{Databases, Protocol, Value, client::Client, db::parse_db_index, send_error};
+
+fn bulk(s: &str) -> Value {
+    Value::BulkString(s.as_bytes().to_vec())
+}
+
+/// `HELLO [protover [AUTH username password] [SETNAME clientname]]`
+///
+/// Switches the connection to the requested protocol and replies with the
+/// server information map, encoded in the protocol that was just chosen.
+pub fn hello(args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let mut args = args.iter();
+    let mut protocol = client.protocol;
+    if let Some(protover) = args.next() {
+        protocol = match std::str::from_utf8(protover)
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+        {
+            Some(2) => Protocol::Resp2,
+            Some(3) => Protocol::Resp3,
+            Some(_) => return send_error(client, "NOPROTO unsupported protocol version"),
+            None => {
+                return send_error(
+                    client,
+                    "ERR Protocol version is not an integer or out of range",
+                );
+            }
+        };
+    }
+    let mut name = None;
+    while let Some(opt) = args.next() {
+        if opt.eq_ignore_ascii_case(b"auth") {
+            let (Some(user), Some(_pass)) = (args.next(), args.next()) else {
+                return send_error(client, "ERR Syntax error in HELLO option 'auth'");
+            };
+            // there are no ACLs, the default user accepts any password
+            if user.as_slice() != b"default" {
+                return send_error(
+                    client,
+                    "WRONGPASS invalid username-password pair or user is disabled.",
+                );
+            }
+        } else if opt.eq_ignore_ascii_case(b"setname") {
+            let Some(clientname) = args.next() else {
+                return send_error(client, "ERR Syntax error in HELLO option 'setname'");
+            };
+            if clientname.iter().any(|b| *b <= b' ' || *b > b'~') {
+                return send_error(
+                    client,
+                    "ERR Client names cannot contain spaces, newlines or special characters.",
+                );
+            }
+            name = Some(clientname.clone());
+        } else {
+            return send_error(
+                client,
+                &format!(
+                    "ERR Syntax error in HELLO option '{}'",
+                    String::from_utf8_lossy(opt)
+                ),
+            );
+        }
+    }
+    if let Some(name) = name {
+        client.name = (!name.is_empty()).then_some(name);
+    }
+    client.protocol = protocol;
+
+    let info = vec![
+        (bulk("server"), bulk("redis_oxide")),
+        (bulk("version"), bulk(env!("CARGO_PKG_VERSION"))),
+        (bulk("proto"), Value::Integer(protocol.version())),
+        (bulk("id"), Value::Integer(client.id as i64)),
+        (bulk("mode"), bulk("standalone")),
+        (bulk("role"), bulk("master")),
+        (bulk("modules"), Value::Array(Vec::new())),
+    ];
+    client.send(&Value::Map(info))
+}
+
+/// `SELECT index`
+pub fn select(dbs: Databases, index: &[u8], client: &mut Client) -> Result<()> {
+    match parse_db_index(index, &dbs) {
+        Ok(index) => {
+            client.db = index;
+            client.send(&Value::String("OK".to_string()))
+        }
+        Err(err) => send_error(client, err),
+    }
+}
@@ -0,0 +1,457 @@
+use std::io::Result;
+
+use crate::{
+    Data, MyFloat, NOT_A_FLOAT, NOT_AN_INTEGER, NullKind, SYNTAX_ERROR, Value,
+    client::Client,
+    geo::{self, INVALID_UNIT, Point, Shape, decode_score, hash_string, parse_unit},
+    object::{Object, WRONGTYPE},
+    send_error,
+    util::{parse_float, parse_int},
+    zset::{ScoreBound, ScoreRange, SortedSet},
+};
+
+/// Parses a longitude and a latitude, checking that they can be indexed.
+fn parse_point(long: &[u8], lat: &[u8]) -> std::result::Result<Point, String> {
+    let (Some(long), Some(lat)) = (parse_float(long), parse_float(lat)) else {
+        return Err(NOT_A_FLOAT.to_string());
+    };
+    if !geo::is_valid((long, lat)) {
+        return Err(format!(
+            "ERR invalid longitude,latitude pair {long:.6},{lat:.6}"
+        ));
+    }
+    Ok((long, lat))
+}
+
+/// Distances are replied with a fixed precision of a tenth of a millimeter
+/// when the unit is meters.
+fn distance_reply(distance: f64) -> Value {
+    Value::BulkString(format!("{distance:.4}").into_bytes())
+}
+
+fn point_reply((long, lat): Point) -> Value {
+    Value::Array(vec![
+        Value::Double(MyFloat::Real(long)),
+        Value::Double(MyFloat::Real(lat)),
+    ])
+}
+
+/// `GEOADD key [NX|XX] [CH] longitude latitude member [longitude latitude
+/// member ...]`
+pub fn geoadd(data: Data, key: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let (mut nx, mut xx, mut ch) = (false, false, false);
+    let mut flags = 0;
+    for arg in args {
+        match arg.to_ascii_lowercase().as_slice() {
+            b"nx" => nx = true,
+            b"xx" => xx = true,
+            b"ch" => ch = true,
+            _ => break,
+        }
+        flags += 1;
+    }
+    let triples = &args[flags..];
+    if triples.is_empty() || !triples.len().is_multiple_of(3) || (nx && xx) {
+        return send_error(client, SYNTAX_ERROR);
+    }
+    let mut places = Vec::with_capacity(triples.len() / 3);
+    for triple in triples.chunks(3) {
+        match parse_point(&triple[0], &triple[1]) {
+            Ok(point) => places.push((geo::score(point), &triple[2])),
+            Err(err) => return send_error(client, &err),
+        }
+    }
+
+    let mut lock = data.lock().unwrap();
+    let Ok(zset) = lock.get_or_create_zset(key) else {
+        return send_error(client, WRONGTYPE);
+    };
+    let (mut added, mut changed) = (0, 0);
+    for (score, member) in places {
+        match zset.score(member) {
+            Some(_) if nx => {}
+            Some(current) => {
+                if current != score {
+                    zset.insert(member.clone(), score);
+                    changed += 1;
+                }
+            }
+            None if xx => {}
+            None => {
+                zset.insert(member.clone(), score);
+                added += 1;
+            }
+        }
+    }
+    lock.remove_if_empty(key);
+    lock.signal_ready(key);
+    client.send(&Value::Integer(if ch { added + changed } else { added }))
+}
+
+/// Runs `reply` on the sorted set stored at `key`, a missing key reading as
+/// an empty one.
+fn with_zset(
+    data: Data,
+    key: &[u8],
+    client: &mut Client,
+    reply: impl FnOnce(&SortedSet) -> Value,
+) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    match lock.get_zset(key) {
+        Ok(Some(zset)) => client.send(&reply(zset)),
+        Ok(None) => client.send(&reply(&SortedSet::new())),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+/// `GEODIST key member1 member2 [M|KM|FT|MI]`
+pub fn geodist(
+    data: Data,
+    key: &[u8],
+    member1: &[u8],
+    member2: &[u8],
+    unit: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let unit = match unit {
+        [] => 1.0,
+        [unit] => match parse_unit(unit) {
+            Some(unit) => unit,
+            None => return send_error(client, INVALID_UNIT),
+        },
+        _ => return send_error(client, SYNTAX_ERROR),
+    };
+    with_zset(data, key, client, |zset| {
+        match (zset.score(member1), zset.score(member2)) {
+            (Some(from), Some(to)) => {
+                distance_reply(geo::distance(decode_score(from), decode_score(to)) / unit)
+            }
+            _ => Value::Null(NullKind::BulkString),
+        }
+    })
+}
+
+/// `GEOPOS key [member ...]`
+pub fn geopos(data: Data, key: &[u8], members: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    with_zset(data, key, client, |zset| {
+        let positions = members
+            .iter()
+            .map(|member| match zset.score(member) {
+                Some(score) => point_reply(decode_score(score)),
+                None => Value::Null(NullKind::Array),
+            })
+            .collect();
+        Value::Array(positions)
+    })
+}
+
+/// `GEOHASH key [member ...]`
+pub fn geohash(data: Data, key: &[u8], members: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    with_zset(data, key, client, |zset| {
+        let hashes = members
+            .iter()
+            .map(|member| match zset.score(member) {
+                Some(score) => Value::BulkString(hash_string(score)),
+                None => Value::Null(NullKind::BulkString),
+            })
+            .collect();
+        Value::Array(hashes)
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Center {
+    Member(Vec<u8>),
+    Point(Point),
+}
+
+/// Arguments of GEOSEARCH and GEOSEARCHSTORE.
+#[derive(Debug, Clone, PartialEq)]
+struct SearchOptions {
+    center: Center,
+    shape: Shape,
+    /// Meters per unit of the sizes given and the distances replied.
+    unit: f64,
+    /// `Some(true)` for DESC, `Some(false)` for ASC.
+    desc: Option<bool>,
+    count: Option<usize>,
+    any: bool,
+    withcoord: bool,
+    withdist: bool,
+    withhash: bool,
+    storedist: bool,
+}
+
+/// Reads the size and unit of a BYRADIUS or BYBOX, returning them with how
+/// many arguments they took.
+fn parse_shape(
+    args: &[Vec<u8>],
+    radius: bool,
+) -> std::result::Result<(Shape, f64, usize), &'static str> {
+    let sizes = if radius { 1 } else { 2 };
+    let Some(args) = args.get(..sizes + 1) else {
+        return Err(SYNTAX_ERROR);
+    };
+    let size = |arg: &[u8], err| parse_float(arg).ok_or(err);
+    let shape = if radius {
+        let radius = size(&args[0], "ERR need numeric radius")?;
+        if radius < 0.0 {
+            return Err("ERR radius cannot be negative");
+        }
+        Shape::Radius(radius)
+    } else {
+        let width = size(&args[0], "ERR need numeric width")?;
+        let height = size(&args[1], "ERR need numeric height")?;
+        if width < 0.0 || height < 0.0 {
+            return Err("ERR height or width cannot be negative");
+        }
+        Shape::Box { width, height }
+    };
+    let unit = parse_unit(&args[sizes]).ok_or(INVALID_UNIT)?;
+    Ok((shape, unit, sizes + 1))
+}
+
+fn parse_search_options(
+    args: &[Vec<u8>],
+    command: &str,
+    store: bool,
+) -> std::result::Result<SearchOptions, String> {
+    let mut center = None;
+    let mut shape = None;
+    let mut desc = None;
+    let mut count = None;
+    let (mut any, mut storedist) = (false, false);
+    let (mut withcoord, mut withdist, mut withhash) = (false, false, false);
+    let exactly_one = |what| format!("ERR exactly one of {what} can be specified for {command}");
+    let mut i = 0;
+    while i < args.len() {
+        let rest = &args[i + 1..];
+        match args[i].to_ascii_lowercase().as_slice() {
+            b"frommember" if !rest.is_empty() => {
+                if center.is_some() {
+                    return Err(exactly_one("FROMMEMBER or FROMLONLAT"));
+                }
+                center = Some(Center::Member(rest[0].clone()));
+                i += 1;
+            }
+            b"fromlonlat" if rest.len() >= 2 => {
+                if center.is_some() {
+                    return Err(exactly_one("FROMMEMBER or FROMLONLAT"));
+                }
+                center = Some(Center::Point(parse_point(&rest[0], &rest[1])?));
+                i += 2;
+            }
+            option @ (b"byradius" | b"bybox") => {
+                if shape.is_some() {
+                    return Err(exactly_one("BYRADIUS and BYBOX"));
+                }
+                let (parsed, unit, taken) = parse_shape(rest, option == b"byradius")?;
+                shape = Some((parsed, unit));
+                i += taken;
+            }
+            b"asc" => desc = Some(false),
+            b"desc" => desc = Some(true),
+            b"count" if !rest.is_empty() => {
+                match parse_int(&rest[0]) {
+                    Some(n) if n > 0 => count = Some(n as usize),
+                    Some(_) => return Err("ERR COUNT must be > 0".to_string()),
+                    None => return Err(NOT_AN_INTEGER.to_string()),
+                }
+                i += 1;
+            }
+            b"any" => any = true,
+            b"withcoord" => withcoord = true,
+            b"withdist" => withdist = true,
+            b"withhash" => withhash = true,
+            b"storedist" if store => storedist = true,
+            _ => return Err(SYNTAX_ERROR.to_string()),
+        }
+        i += 1;
+    }
+    let Some(center) = center else {
+        return Err(exactly_one("FROMMEMBER or FROMLONLAT"));
+    };
+    let Some((shape, unit)) = shape else {
+        return Err(exactly_one("BYRADIUS and BYBOX"));
+    };
+    if any && count.is_none() {
+        return Err("ERR the ANY argument requires COUNT argument".to_string());
+    }
+    if store && (withcoord || withdist || withhash) {
+        return Err(format!(
+            "ERR {command} is not compatible with WITHDIST, WITHHASH and WITHCOORD options"
+        ));
+    }
+    let shape = match shape {
+        Shape::Radius(radius) => Shape::Radius(radius * unit),
+        Shape::Box { width, height } => Shape::Box {
+            width: width * unit,
+            height: height * unit,
+        },
+    };
+    Ok(SearchOptions {
+        center,
+        shape,
+        unit,
+        desc,
+        count,
+        any,
+        withcoord,
+        withdist,
+        withhash,
+        storedist,
+    })
+}
+
+/// A place found by a search.
+struct Found {
+    member: Vec<u8>,
+    score: f64,
+    /// Distance to the center, in meters.
+    distance: f64,
+    point: Point,
+}
+
+/// Finds the places of `zset` within the search shape, scanning the cells
+/// around the center, sorted as asked.
+fn search(
+    zset: &SortedSet,
+    options: &SearchOptions,
+) -> std::result::Result<Vec<Found>, &'static str> {
+    let center = match &options.center {
+        Center::Point(point) => *point,
+        Center::Member(member) => match zset.score(member) {
+            Some(score) => decode_score(score),
+            None => return Err("ERR could not decode requested zset member"),
+        },
+    };
+    // ANY stops at the first places found, in no particular order
+    let limit = options.count.filter(|_| options.any);
+    let mut found = Vec::new();
+    'cells: for cell in options.shape.cells(center) {
+        let (min, max) = cell.score_range();
+        let range = ScoreRange {
+            min: ScoreBound {
+                value: min,
+                exclusive: false,
+            },
+            max: ScoreBound {
+                value: max,
+                exclusive: true,
+            },
+        };
+        let ranks = zset.score_range(&range);
+        for (member, score) in zset.iter_from(ranks.start, false).take(ranks.len()) {
+            if limit.is_some_and(|limit| found.len() >= limit) {
+                break 'cells;
+            }
+            let point = decode_score(score);
+            if let Some(distance) = options.shape.distance_if_inside(center, point) {
+                found.push(Found {
+                    member: member.to_vec(),
+                    score,
+                    distance,
+                    point,
+                });
+            }
+        }
+    }
+    // a COUNT without ANY keeps the closest places
+    let desc = match options.desc {
+        None if options.count.is_some() && !options.any => Some(false),
+        desc => desc,
+    };
+    match desc {
+        Some(false) => found.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+        Some(true) => found.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+        None => {}
+    }
+    if let Some(count) = options.count {
+        found.truncate(count);
+    }
+    Ok(found)
+}
+
+/// `GEOSEARCH key FROMMEMBER member|FROMLONLAT longitude latitude
+/// BYRADIUS radius unit|BYBOX width height unit [ASC|DESC] [COUNT count
+/// [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]`
+pub fn geosearch(data: Data, key: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let options = match parse_search_options(args, "GEOSEARCH", false) {
+        Ok(options) => options,
+        Err(err) => return send_error(client, &err),
+    };
+    let mut lock = data.lock().unwrap();
+    let found = match lock.get_zset(key) {
+        Ok(Some(zset)) => search(zset, &options),
+        Ok(None) => Ok(Vec::new()),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let found = match found {
+        Ok(found) => found,
+        Err(err) => return send_error(client, err),
+    };
+    let plain = !(options.withdist || options.withhash || options.withcoord);
+    let reply = found
+        .into_iter()
+        .map(|place| {
+            let member = Value::BulkString(place.member);
+            if plain {
+                return member;
+            }
+            let mut reply = vec![member];
+            if options.withdist {
+                reply.push(distance_reply(place.distance / options.unit));
+            }
+            if options.withhash {
+                reply.push(Value::Integer(place.score as i64));
+            }
+            if options.withcoord {
+                reply.push(point_reply(place.point));
+            }
+            Value::Array(reply)
+        })
+        .collect();
+    client.send(&Value::Array(reply))
+}
+
+/// `GEOSEARCHSTORE destination source FROMMEMBER member|FROMLONLAT
+/// longitude latitude BYRADIUS radius unit|BYBOX width height unit
+/// [ASC|DESC] [COUNT count [ANY]] [STOREDIST]`
+pub fn geosearchstore(
+    data: Data,
+    destination: &[u8],
+    source: &[u8],
+    args: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let options = match parse_search_options(args, "GEOSEARCHSTORE", true) {
+        Ok(options) => options,
+        Err(err) => return send_error(client, &err),
+    };
+    let mut lock = data.lock().unwrap();
+    let found = match lock.get_zset(source) {
+        Ok(Some(zset)) => search(zset, &options),
+        Ok(None) => Ok(Vec::new()),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let found = match found {
+        Ok(found) => found,
+        Err(err) => return send_error(client, err),
+    };
+    let mut zset = SortedSet::new();
+    for place in found {
+        let score = if options.storedist {
+            place.distance / options.unit
+        } else {
+            place.score
+        };
+        zset.insert(place.member, score);
+    }
+    let len = zset.len();
+    if zset.is_empty() {
+        lock.remove(destination);
+    } else {
+        lock.insert(destination.to_vec(), Object::ZSet(zset));
+    }
+    client.send(&Value::Integer(len as i64))
+}
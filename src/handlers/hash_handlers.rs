@@ -0,0 +1,189 @@
+use std::io::Result;
+
+use crate::{
+    Data, NOT_AN_INTEGER, NullKind, Value,
+    client::Client,
+    object::{Hash, WRONGTYPE},
+    scan::{ScanTarget, parse_scan_args},
+    send_error,
+    util::parse_int,
+};
+
+/// `HSET key field value [field value ...]`
+pub fn hset(data: Data, key: &[u8], pairs: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    if !pairs.len().is_multiple_of(2) {
+        return send_error(client, "ERR wrong number of arguments for 'hset' command");
+    }
+    let mut lock = data.lock().unwrap();
+    let Ok(hash) = lock.get_or_create_hash(key) else {
+        return send_error(client, WRONGTYPE);
+    };
+    let mut created = 0;
+    for pair in pairs.chunks(2) {
+        if hash.insert(pair[0].clone(), pair[1].clone()).is_none() {
+            created += 1;
+        }
+    }
+    client.send(&Value::Integer(created))
+}
+
+pub fn hget(data: Data, key: &[u8], field: &[u8], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    match lock.get_hash(key) {
+        Ok(hash) => client.send(
+            &hash
+                .and_then(|h| h.get(field))
+                .map_or(Value::Null(NullKind::BulkString), |v| {
+                    Value::BulkString(v.clone())
+                }),
+        ),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+/// `HMGET key field [field ...]`
+pub fn hmget(data: Data, key: &[u8], fields: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let Ok(hash) = lock.get_hash(key) else {
+        return send_error(client, WRONGTYPE);
+    };
+    let values = fields
+        .iter()
+        .map(|field| {
+            hash.and_then(|h| h.get(field))
+                .map_or(Value::Null(NullKind::BulkString), |v| {
+                    Value::BulkString(v.clone())
+                })
+        })
+        .collect();
+    client.send(&Value::Array(values))
+}
+
+/// `HDEL key field [field ...]`
+pub fn hdel(data: Data, key: &[u8], fields: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let hash = match lock.get_hash_mut(key) {
+        Ok(Some(hash)) => hash,
+        Ok(None) => return client.send(&Value::Integer(0)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let removed = fields
+        .iter()
+        .filter(|field| hash.remove(*field).is_some())
+        .count();
+    lock.remove_if_empty(key);
+    client.send(&Value::Integer(removed as i64))
+}
+
+/// Runs `reply` against the hash at `key`, an empty one if it doesn't exist.
+fn with_hash(
+    data: Data,
+    key: &[u8],
+    client: &mut Client,
+    reply: impl FnOnce(&Hash) -> Value,
+) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    match lock.get_hash(key) {
+        Ok(Some(hash)) => client.send(&reply(hash)),
+        Ok(None) => client.send(&reply(&Hash::new())),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+pub fn hgetall(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    with_hash(data, key, client, |hash| {
+        Value::Map(
+            hash.iter()
+                .map(|(f, v)| (Value::BulkString(f.clone()), Value::BulkString(v.clone())))
+                .collect(),
+        )
+    })
+}
+
+pub fn hkeys(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    with_hash(data, key, client, |hash| {
+        Value::Array(hash.keys().map(|f| Value::BulkString(f.clone())).collect())
+    })
+}
+
+pub fn hvals(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    with_hash(data, key, client, |hash| {
+        Value::Array(
+            hash.values()
+                .map(|v| Value::BulkString(v.clone()))
+                .collect(),
+        )
+    })
+}
+
+pub fn hlen(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    with_hash(data, key, client, |hash| Value::Integer(hash.len() as i64))
+}
+
+pub fn hexists(data: Data, key: &[u8], field: &[u8], client: &mut Client) -> Result<()> {
+    with_hash(data, key, client, |hash| {
+        Value::Integer(hash.contains_key(field) as i64)
+    })
+}
+
+pub fn hincrby(
+    data: Data,
+    key: &[u8],
+    field: &[u8],
+    increment: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    let Some(increment) = parse_int(increment) else {
+        return send_error(client, NOT_AN_INTEGER);
+    };
+    let mut lock = data.lock().unwrap();
+    let Ok(hash) = lock.get_or_create_hash(key) else {
+        return send_error(client, WRONGTYPE);
+    };
+    let current = match hash.get(field) {
+        Some(v) => match parse_int(v) {
+            Some(v) => v,
+            None => {
+                lock.remove_if_empty(key);
+                return send_error(client, "ERR hash value is not an integer");
+            }
+        },
+        None => 0,
+    };
+    let Some(new) = current.checked_add(increment) else {
+        lock.remove_if_empty(key);
+        return send_error(client, "ERR increment or decrement would overflow");
+    };
+    hash.insert(field.to_vec(), new.to_string().into_bytes());
+    client.send(&Value::Integer(new))
+}
+
+/// `HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]`
+pub fn hscan(
+    data: Data,
+    key: &[u8],
+    cursor: &[u8],
+    args: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let (cursor, options) = match parse_scan_args(cursor, args, ScanTarget::Hash) {
+        Ok(parsed) => parsed,
+        Err(err) => return send_error(client, &err),
+    };
+    with_hash(data, key, client, |hash| {
+        let mut elements = Vec::new();
+        let next = hash.scan(cursor, options.count, |field, value| {
+            if !options.matches(field) {
+                return;
+            }
+            elements.push(Value::BulkString(field.clone()));
+            if !options.novalues {
+                elements.push(Value::BulkString(value.clone()));
+            }
+        });
+        Value::Array(vec![
+            Value::BulkString(next.to_string().into_bytes()),
+            Value::Array(elements),
+        ])
+    })
+}
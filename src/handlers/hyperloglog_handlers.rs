@@ -0,0 +1,101 @@
+use std::io::Result;
+
+use crate::{
+    Data, Value,
+    client::Client,
+    hyperloglog::{
+        self, INVALID_HLL, REGISTERS, Registers, count, count_registers, invalidate_cache,
+        is_dense, is_valid, merge_into, store,
+    },
+    object::{Object, WRONGTYPE},
+    send_error,
+};
+
+/// `PFADD key [element ...]`
+pub fn pfadd(data: Data, key: &[u8], elements: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    // creating the key is a change even without elements
+    let mut updated = false;
+    if !lock.contains_key(key) {
+        lock.insert(key.to_vec(), Object::String(hyperloglog::new()));
+        updated = true;
+    }
+    let hll = match lock.get_string_mut(key) {
+        Ok(Some(hll)) if is_valid(hll) => hll,
+        Ok(_) => return send_error(client, INVALID_HLL),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    for element in elements {
+        match hyperloglog::add(hll, element) {
+            Ok(changed) => updated |= changed,
+            Err(err) => return send_error(client, err),
+        }
+    }
+    if updated {
+        invalidate_cache(hll);
+    }
+    client.send(&Value::Integer(updated as i64))
+}
+
+/// `PFCOUNT key [key ...]`
+pub fn pfcount(data: Data, key: &[u8], keys: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    if keys.is_empty() {
+        // a single counter caches its cardinality
+        let card = match lock.get_string_mut(key) {
+            Ok(Some(hll)) if is_valid(hll) => count(hll),
+            Ok(Some(_)) => return send_error(client, INVALID_HLL),
+            Ok(None) => Ok(0),
+            Err(_) => return send_error(client, WRONGTYPE),
+        };
+        return match card {
+            Ok(card) => client.send(&Value::Integer(card as i64)),
+            Err(err) => send_error(client, err),
+        };
+    }
+    let mut max: Registers = [0; REGISTERS];
+    for key in std::iter::once(key).chain(keys.iter().map(Vec::as_slice)) {
+        let merged = match lock.get_string(key) {
+            Ok(Some(hll)) if is_valid(&hll) => merge_into(&mut max, &hll),
+            Ok(Some(_)) => return send_error(client, INVALID_HLL),
+            Ok(None) => continue,
+            Err(_) => return send_error(client, WRONGTYPE),
+        };
+        if let Err(err) = merged {
+            return send_error(client, err);
+        }
+    }
+    client.send(&Value::Integer(count_registers(&max) as i64))
+}
+
+/// `PFMERGE destkey [sourcekey ...]`
+pub fn pfmerge(data: Data, destkey: &[u8], sources: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let mut max: Registers = [0; REGISTERS];
+    // the result is dense as soon as one of the counters is
+    let mut dense = false;
+    for key in std::iter::once(destkey).chain(sources.iter().map(Vec::as_slice)) {
+        let merged = match lock.get_string(key) {
+            Ok(Some(hll)) if is_valid(&hll) => {
+                dense |= is_dense(&hll);
+                merge_into(&mut max, &hll)
+            }
+            Ok(Some(_)) => return send_error(client, INVALID_HLL),
+            Ok(None) => continue,
+            Err(_) => return send_error(client, WRONGTYPE),
+        };
+        if let Err(err) = merged {
+            return send_error(client, err);
+        }
+    }
+    if !lock.contains_key(destkey) {
+        lock.insert(destkey.to_vec(), Object::String(hyperloglog::new()));
+    }
+    let Ok(Some(hll)) = lock.get_string_mut(destkey) else {
+        unreachable!("the destination was checked to be a string");
+    };
+    match store(hll, &max, dense) {
+        Ok(()) => client.send(&Value::String("OK".to_string())),
+        Err(err) => send_error(client, err),
+    }
+}
@@ -0,0 +1,458 @@
+use std::io::Result;
+
+use crate::{
+    Data, Databases, NOT_AN_INTEGER, NullKind, SYNTAX_ERROR, Value,
+    client::Client,
+    db::{Db, lock_pair, now_ms, parse_db_index},
+    glob::glob_match,
+    object::Object,
+    scan::{ScanTarget, parse_scan_args},
+    send_error,
+    util::parse_int,
+};
+
+/// Shared implementation of EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT, the
+/// command name deciding the unit of `when` and whether it is a unix
+/// timestamp or a time from now.
+fn expire_generic(
+    data: Data,
+    key: &[u8],
+    when: &[u8],
+    options: &[Vec<u8>],
+    command: &str,
+    client: &mut Client,
+) -> Result<()> {
+    let (unit, absolute) = match command {
+        "expire" => (1000, false),
+        "pexpire" => (1, false),
+        "expireat" => (1000, true),
+        _ => (1, true),
+    };
+    let (mut nx, mut xx, mut gt, mut lt) = (false, false, false, false);
+    for opt in options {
+        match opt.to_ascii_lowercase().as_slice() {
+            b"nx" => nx = true,
+            b"xx" => xx = true,
+            b"gt" => gt = true,
+            b"lt" => lt = true,
+            _ => {
+                return send_error(
+                    client,
+                    &format!("ERR Unsupported option {}", String::from_utf8_lossy(opt)),
+                );
+            }
+        }
+    }
+    if nx && (xx || gt || lt) {
+        return send_error(
+            client,
+            "ERR NX and XX, GT or LT options at the same time are not compatible",
+        );
+    }
+    if gt && lt {
+        return send_error(
+            client,
+            "ERR GT and LT options at the same time are not compatible",
+        );
+    }
+    let Some(when) = parse_int(when) else {
+        return send_error(client, NOT_AN_INTEGER);
+    };
+    let at = when.checked_mul(unit).and_then(|ms| {
+        if absolute {
+            Some(ms)
+        } else {
+            ms.checked_add(now_ms() as i64)
+        }
+    });
+    let Some(at) = at else {
+        return send_error(
+            client,
+            &format!("ERR invalid expire time in '{}' command", command),
+        );
+    };
+
+    let mut lock = data.lock().unwrap();
+    if !lock.contains_key(key) {
+        return client.send(&Value::Integer(0));
+    }
+    let current = lock.expire_at(key);
+    let allowed = match current {
+        _ if nx => current.is_none(),
+        // a key without expire counts as one that never expires
+        None => !(xx || gt),
+        Some(current) => !((gt && at <= current as i64) || (lt && at >= current as i64)),
+    };
+    if !allowed {
+        return client.send(&Value::Integer(0));
+    }
+    lock.set_expire(key, at.max(0) as u64);
+    client.send(&Value::Integer(1))
+}
+
+pub fn expire(
+    data: Data,
+    key: &[u8],
+    seconds: &[u8],
+    options: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    expire_generic(data, key, seconds, options, "expire", client)
+}
+
+pub fn pexpire(
+    data: Data,
+    key: &[u8],
+    milliseconds: &[u8],
+    options: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    expire_generic(data, key, milliseconds, options, "pexpire", client)
+}
+
+pub fn expireat(
+    data: Data,
+    key: &[u8],
+    timestamp: &[u8],
+    options: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    expire_generic(data, key, timestamp, options, "expireat", client)
+}
+
+pub fn pexpireat(
+    data: Data,
+    key: &[u8],
+    timestamp: &[u8],
+    options: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    expire_generic(data, key, timestamp, options, "pexpireat", client)
+}
+
+/// Remaining time to live of `key` in milliseconds, -2 if the key doesn't
+/// exist and -1 if it has no expire.
+fn ttl_ms(data: Data, key: &[u8]) -> i64 {
+    let mut lock = data.lock().unwrap();
+    if !lock.contains_key(key) {
+        return -2;
+    }
+    match lock.expire_at(key) {
+        Some(at) => at.saturating_sub(now_ms()) as i64,
+        None => -1,
+    }
+}
+
+pub fn ttl(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    let ttl = ttl_ms(data, key);
+    let ttl = if ttl < 0 { ttl } else { (ttl + 500) / 1000 };
+    client.send(&Value::Integer(ttl))
+}
+
+pub fn pttl(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    client.send(&Value::Integer(ttl_ms(data, key)))
+}
+
+pub fn persist(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    let persisted = data.lock().unwrap().persist(key);
+    client.send(&Value::Integer(persisted as i64))
+}
+
+/// `EXISTS key [key ...]`, a key given several times counting every time.
+pub fn exists(
+    data: Data,
+    keys: &mut impl Iterator<Item = Vec<u8>>,
+    client: &mut Client,
+) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let count = keys.filter(|key| lock.contains_key(key)).count();
+    client.send(&Value::Integer(count as i64))
+}
+
+/// `TOUCH key [key ...]`. Access times aren't tracked, so this only counts
+/// the keys that exist.
+pub fn touch(
+    data: Data,
+    keys: &mut impl Iterator<Item = Vec<u8>>,
+    client: &mut Client,
+) -> Result<()> {
+    exists(data, keys, client)
+}
+
+pub fn type_(data: Data, args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let [key] = args else {
+        return send_error(client, "ERR wrong number of arguments for 'type' command");
+    };
+    let mut lock = data.lock().unwrap();
+    let name = lock.get(key).map_or("none", Object::type_name);
+    client.send(&Value::String(name.to_string()))
+}
+
+/// Stores `value` under `key` with the given absolute expire, returning
+/// what the key held before.
+fn replace(db: &mut Db, key: &[u8], value: Object, expire: Option<u64>) -> Option<Object> {
+    let old = db.remove(key);
+    db.insert(key.to_vec(), value);
+    if let Some(at) = expire {
+        db.set_expire(key, at);
+    }
+    old
+}
+
+/// Frees `values` on the rayon pool, away from the keyspace lock.
+fn free_in_background<T: Send + 'static>(values: T) {
+    rayon::spawn(move || drop(values));
+}
+
+fn rename_generic(
+    data: Data,
+    key: &[u8],
+    newkey: &[u8],
+    nx: bool,
+    client: &mut Client,
+) -> Result<()> {
+    let reply = |renamed: bool| {
+        if nx {
+            Value::Integer(renamed as i64)
+        } else {
+            Value::String("OK".to_string())
+        }
+    };
+    let mut lock = data.lock().unwrap();
+    if !lock.contains_key(key) {
+        return send_error(client, "ERR no such key");
+    }
+    if key == newkey || (nx && lock.contains_key(newkey)) {
+        return client.send(&reply(false));
+    }
+    let expire = lock.expire_at(key);
+    let value = lock.remove(key).unwrap();
+    replace(&mut lock, newkey, value, expire);
+    client.send(&reply(true))
+}
+
+/// `RENAME key newkey`
+pub fn rename(data: Data, key: &[u8], newkey: &[u8], client: &mut Client) -> Result<()> {
+    rename_generic(data, key, newkey, false, client)
+}
+
+/// `RENAMENX key newkey`
+pub fn renamenx(data: Data, key: &[u8], newkey: &[u8], client: &mut Client) -> Result<()> {
+    rename_generic(data, key, newkey, true, client)
+}
+
+/// Copies `source` of `src` to `destination` of `dst`, or of `src` itself
+/// without `dst`, returning whether it did.
+fn copy_key(
+    src: &mut Db,
+    dst: Option<&mut Db>,
+    source: &[u8],
+    destination: &[u8],
+    replace_dest: bool,
+) -> bool {
+    let Some(value) = src.get(source).cloned() else {
+        return false;
+    };
+    let expire = src.expire_at(source);
+    let dst = match dst {
+        Some(dst) => dst,
+        None => src,
+    };
+    if !replace_dest && dst.contains_key(destination) {
+        return false;
+    }
+    replace(dst, destination, value, expire);
+    true
+}
+
+/// `COPY source destination [DB destination-db] [REPLACE]`
+pub fn copy(
+    dbs: Databases,
+    source: &[u8],
+    destination: &[u8],
+    options: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let mut replace_dest = false;
+    let mut target = client.db;
+    let mut options = options.iter();
+    while let Some(opt) = options.next() {
+        match opt.to_ascii_lowercase().as_slice() {
+            b"replace" => replace_dest = true,
+            b"db" => {
+                let Some(db) = options.next() else {
+                    return send_error(client, SYNTAX_ERROR);
+                };
+                target = match parse_db_index(db, &dbs) {
+                    Ok(index) => index,
+                    Err(err) => return send_error(client, err),
+                };
+            }
+            _ => return send_error(client, SYNTAX_ERROR),
+        }
+    }
+    let copied = if target == client.db {
+        if source == destination {
+            return send_error(client, "ERR source and destination objects are the same");
+        }
+        let mut lock = dbs[target].lock().unwrap();
+        copy_key(&mut lock, None, source, destination, replace_dest)
+    } else {
+        let (mut src, mut dst) = lock_pair(&dbs, client.db, target);
+        copy_key(&mut src, Some(&mut dst), source, destination, replace_dest)
+    };
+    client.send(&Value::Integer(copied as i64))
+}
+
+/// `UNLINK key [key ...]`, which is DEL with the values freed in the
+/// background.
+pub fn unlink(
+    data: Data,
+    keys: &mut impl Iterator<Item = Vec<u8>>,
+    client: &mut Client,
+) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let values: Vec<Object> = keys.filter_map(|key| lock.remove(&key)).collect();
+    drop(lock);
+    let count = values.len();
+    if count > 0 {
+        free_in_background(values);
+    }
+    client.send(&Value::Integer(count as i64))
+}
+
+pub fn randomkey(data: Data, args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    if !args.is_empty() {
+        return send_error(
+            client,
+            "ERR wrong number of arguments for 'randomkey' command",
+        );
+    }
+    match data.lock().unwrap().random_key() {
+        Some(key) => client.send(&Value::BulkString(key)),
+        None => client.send(&Value::Null(NullKind::BulkString)),
+    }
+}
+
+pub fn dbsize(data: Data, args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    if !args.is_empty() {
+        return send_error(client, "ERR wrong number of arguments for 'dbsize' command");
+    }
+    let len = data.lock().unwrap().len();
+    client.send(&Value::Integer(len as i64))
+}
+
+/// Whether a FLUSHDB or FLUSHALL is to free the values in the background.
+fn parse_flush_mode(args: &[Vec<u8>]) -> Option<bool> {
+    match args {
+        [] => Some(false),
+        [mode] => match mode.to_ascii_lowercase().as_slice() {
+            b"sync" => Some(false),
+            b"async" => Some(true),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `FLUSHDB [ASYNC | SYNC]`
+pub fn flushdb(data: Data, args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let Some(background) = parse_flush_mode(args) else {
+        return send_error(client, SYNTAX_ERROR);
+    };
+    let entries = data.lock().unwrap().flush();
+    if background {
+        free_in_background(entries);
+    } else {
+        drop(entries);
+    }
+    client.send(&Value::String("OK".to_string()))
+}
+
+/// `FLUSHALL [ASYNC | SYNC]`
+pub fn flushall(dbs: Databases, args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let Some(background) = parse_flush_mode(args) else {
+        return send_error(client, SYNTAX_ERROR);
+    };
+    let entries: Vec<_> = dbs.iter().map(|db| db.lock().unwrap().flush()).collect();
+    if background {
+        free_in_background(entries);
+    } else {
+        drop(entries);
+    }
+    client.send(&Value::String("OK".to_string()))
+}
+
+/// `SWAPDB index1 index2`
+pub fn swapdb(dbs: Databases, index1: &[u8], index2: &[u8], client: &mut Client) -> Result<()> {
+    let Some(index1) = parse_int(index1) else {
+        return send_error(client, "ERR invalid first DB index");
+    };
+    let Some(index2) = parse_int(index2) else {
+        return send_error(client, "ERR invalid second DB index");
+    };
+    let in_range = |index: i64| usize::try_from(index).ok().filter(|i| *i < dbs.len());
+    let (Some(index1), Some(index2)) = (in_range(index1), in_range(index2)) else {
+        return send_error(client, "ERR DB index is out of range");
+    };
+    if index1 != index2 {
+        let (mut first, mut second) = lock_pair(&dbs, index1, index2);
+        first.swap_contents(&mut second);
+    }
+    client.send(&Value::String("OK".to_string()))
+}
+
+/// `MOVE key db`, which keeps the expire of the key.
+pub fn move_(dbs: Databases, args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let [key, db] = args else {
+        return send_error(client, "ERR wrong number of arguments for 'move' command");
+    };
+    let target = match parse_db_index(db, &dbs) {
+        Ok(index) => index,
+        Err(err) => return send_error(client, err),
+    };
+    if target == client.db {
+        return send_error(client, "ERR source and destination objects are the same");
+    }
+    let (mut src, mut dst) = lock_pair(&dbs, client.db, target);
+    if !src.contains_key(key) || dst.contains_key(key) {
+        return client.send(&Value::Integer(0));
+    }
+    let expire = src.expire_at(key);
+    let value = src.remove(key).unwrap();
+    replace(&mut dst, key, value, expire);
+    client.send(&Value::Integer(1))
+}
+
+/// `KEYS pattern`
+pub fn keys(data: Data, pattern: &[u8], client: &mut Client) -> Result<()> {
+    let lock = data.lock().unwrap();
+    let keys = lock
+        .iter()
+        .filter(|(key, _)| glob_match(pattern, key, false))
+        .map(|(key, _)| Value::BulkString(key.clone()))
+        .collect();
+    client.send(&Value::Array(keys))
+}
+
+/// `SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]`
+pub fn scan(data: Data, cursor: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let (cursor, options) = match parse_scan_args(cursor, args, ScanTarget::Keys) {
+        Ok(parsed) => parsed,
+        Err(err) => return send_error(client, &err),
+    };
+    let lock = data.lock().unwrap();
+    let mut keys = Vec::new();
+    let next = lock.scan(cursor, options.count, |key, value| {
+        let type_matches = options
+            .object_type
+            .is_none_or(|name| value.type_name() == name);
+        if options.matches(key) && type_matches {
+            keys.push(Value::BulkString(key.clone()));
+        }
+    });
+    client.send(&Value::Array(vec![
+        Value::BulkString(next.to_string().into_bytes()),
+        Value::Array(keys),
+    ]))
+}
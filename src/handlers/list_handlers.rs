@@ -0,0 +1,419 @@
+use std::{collections::VecDeque, io::Result};
+
+use crate::{
+    Data, NOT_AN_INTEGER, NullKind, SYNTAX_ERROR, Value,
+    blocking::{Attempt, block_on, parse_timeout},
+    client::Client,
+    db::Db,
+    object::WRONGTYPE,
+    send_error,
+    util::{normalize_range, parse_int},
+};
+
+/// End of a list a command works on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    pub fn parse(arg: &[u8]) -> Option<Side> {
+        match arg.to_ascii_lowercase().as_slice() {
+            b"left" => Some(Side::Left),
+            b"right" => Some(Side::Right),
+            _ => None,
+        }
+    }
+
+    pub fn pop(self, list: &mut VecDeque<Vec<u8>>) -> Option<Vec<u8>> {
+        match self {
+            Side::Left => list.pop_front(),
+            Side::Right => list.pop_back(),
+        }
+    }
+
+    pub fn push(self, list: &mut VecDeque<Vec<u8>>, element: Vec<u8>) {
+        match self {
+            Side::Left => list.push_front(element),
+            Side::Right => list.push_back(element),
+        }
+    }
+}
+
+/// Turns a possibly negative index into an offset from the head, `None` if
+/// it falls outside of a list of `len` elements.
+fn normalize_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+fn push(
+    data: Data,
+    key: &[u8],
+    elements: &[Vec<u8>],
+    side: Side,
+    client: &mut Client,
+) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let Ok(list) = lock.get_or_create_list(key) else {
+        return send_error(client, WRONGTYPE);
+    };
+    for element in elements {
+        side.push(list, element.clone());
+    }
+    let len = list.len() as i64;
+    lock.signal_ready(key);
+    client.send(&Value::Integer(len))
+}
+
+pub fn lpush(data: Data, key: &[u8], elements: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    push(data, key, elements, Side::Left, client)
+}
+
+pub fn rpush(data: Data, key: &[u8], elements: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    push(data, key, elements, Side::Right, client)
+}
+
+fn pop(data: Data, key: &[u8], count: &[Vec<u8>], side: Side, client: &mut Client) -> Result<()> {
+    let count = match count {
+        [] => None,
+        [count] => match parse_int(count) {
+            Some(count) if count >= 0 => Some(count as usize),
+            _ => return send_error(client, "ERR value is out of range, must be positive"),
+        },
+        _ => return send_error(client, SYNTAX_ERROR),
+    };
+    let mut lock = data.lock().unwrap();
+    let list = match lock.get_list_mut(key) {
+        Ok(Some(list)) => list,
+        Ok(None) if count.is_some() => return client.send(&Value::Null(NullKind::Array)),
+        Ok(None) => return client.send(&Value::Null(NullKind::BulkString)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let reply = match count {
+        Some(count) => Value::Array(
+            std::iter::from_fn(|| side.pop(list))
+                .take(count)
+                .map(Value::BulkString)
+                .collect(),
+        ),
+        None => side
+            .pop(list)
+            .map_or(Value::Null(NullKind::BulkString), Value::BulkString),
+    };
+    lock.remove_if_empty(key);
+    client.send(&reply)
+}
+
+pub fn lpop(data: Data, key: &[u8], count: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    pop(data, key, count, Side::Left, client)
+}
+
+pub fn rpop(data: Data, key: &[u8], count: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    pop(data, key, count, Side::Right, client)
+}
+
+pub fn lrange(
+    data: Data,
+    key: &[u8],
+    start: &[u8],
+    stop: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    let (Some(start), Some(stop)) = (parse_int(start), parse_int(stop)) else {
+        return send_error(client, NOT_AN_INTEGER);
+    };
+    let mut lock = data.lock().unwrap();
+    let list = match lock.get_list(key) {
+        Ok(Some(list)) => list,
+        Ok(None) => return client.send(&Value::Array(Vec::new())),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let elements = match normalize_range(start, stop, list.len()) {
+        Some((start, stop)) => list
+            .range(start..=stop)
+            .map(|e| Value::BulkString(e.clone()))
+            .collect(),
+        None => Vec::new(),
+    };
+    client.send(&Value::Array(elements))
+}
+
+pub fn llen(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    match lock.get_list(key) {
+        Ok(list) => client.send(&Value::Integer(list.map_or(0, VecDeque::len) as i64)),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+pub fn lindex(data: Data, key: &[u8], index: &[u8], client: &mut Client) -> Result<()> {
+    let Some(index) = parse_int(index) else {
+        return send_error(client, NOT_AN_INTEGER);
+    };
+    let mut lock = data.lock().unwrap();
+    let list = match lock.get_list(key) {
+        Ok(Some(list)) => list,
+        Ok(None) => return client.send(&Value::Null(NullKind::BulkString)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let element = normalize_index(index, list.len()).map(|i| list[i].clone());
+    client.send(&element.map_or(Value::Null(NullKind::BulkString), Value::BulkString))
+}
+
+pub fn lset(
+    data: Data,
+    key: &[u8],
+    index: &[u8],
+    element: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    let Some(index) = parse_int(index) else {
+        return send_error(client, NOT_AN_INTEGER);
+    };
+    let mut lock = data.lock().unwrap();
+    let list = match lock.get_list_mut(key) {
+        Ok(Some(list)) => list,
+        Ok(None) => return send_error(client, "ERR no such key"),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let Some(index) = normalize_index(index, list.len()) else {
+        return send_error(client, "ERR index out of range");
+    };
+    list[index] = element.to_vec();
+    client.send(&Value::String("OK".to_string()))
+}
+
+pub fn lrem(
+    data: Data,
+    key: &[u8],
+    count: &[u8],
+    element: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    let Some(count) = parse_int(count) else {
+        return send_error(client, NOT_AN_INTEGER);
+    };
+    let mut lock = data.lock().unwrap();
+    let list = match lock.get_list_mut(key) {
+        Ok(Some(list)) => list,
+        Ok(None) => return client.send(&Value::Integer(0)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let limit = if count == 0 {
+        usize::MAX
+    } else {
+        count.unsigned_abs() as usize
+    };
+    let mut removed = 0;
+    if count < 0 {
+        let mut i = list.len();
+        while i > 0 && removed < limit {
+            i -= 1;
+            if list[i] == element {
+                list.remove(i);
+                removed += 1;
+            }
+        }
+    } else {
+        let mut i = 0;
+        while i < list.len() && removed < limit {
+            if list[i] == element {
+                list.remove(i);
+                removed += 1;
+            } else {
+                i += 1;
+            }
+        }
+    }
+    lock.remove_if_empty(key);
+    client.send(&Value::Integer(removed as i64))
+}
+
+pub fn ltrim(data: Data, key: &[u8], start: &[u8], stop: &[u8], client: &mut Client) -> Result<()> {
+    let (Some(start), Some(stop)) = (parse_int(start), parse_int(stop)) else {
+        return send_error(client, NOT_AN_INTEGER);
+    };
+    let mut lock = data.lock().unwrap();
+    let list = match lock.get_list_mut(key) {
+        Ok(Some(list)) => list,
+        Ok(None) => return client.send(&Value::String("OK".to_string())),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    match normalize_range(start, stop, list.len()) {
+        Some((start, stop)) => {
+            list.truncate(stop + 1);
+            list.drain(..start);
+        }
+        None => list.clear(),
+    }
+    lock.remove_if_empty(key);
+    client.send(&Value::String("OK".to_string()))
+}
+
+pub fn linsert(
+    data: Data,
+    key: &[u8],
+    position: &[u8],
+    pivot: &[u8],
+    element: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    let offset = match position.to_ascii_lowercase().as_slice() {
+        b"before" => 0,
+        b"after" => 1,
+        _ => return send_error(client, SYNTAX_ERROR),
+    };
+    let mut lock = data.lock().unwrap();
+    let list = match lock.get_list_mut(key) {
+        Ok(Some(list)) => list,
+        Ok(None) => return client.send(&Value::Integer(0)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let Some(index) = list.iter().position(|e| e == pivot) else {
+        return client.send(&Value::Integer(-1));
+    };
+    list.insert(index + offset, element.to_vec());
+    let len = list.len() as i64;
+    client.send(&Value::Integer(len))
+}
+
+/// Pops an element from `source` and pushes it to `destination`, replying
+/// with the element, or `None` if `source` doesn't exist.
+fn move_element(
+    db: &mut Db,
+    source: &[u8],
+    destination: &[u8],
+    wherefrom: Side,
+    whereto: Side,
+) -> Option<Value> {
+    // like Redis, a missing source wins over a destination of the wrong
+    // type: LMOVE replies nil and BLMOVE keeps waiting
+    match db.get_list(source) {
+        Ok(Some(list)) if !list.is_empty() => {}
+        Ok(_) => return None,
+        Err(_) => return Some(Value::Error(WRONGTYPE.to_string())),
+    }
+    if db.get_list(destination).is_err() {
+        return Some(Value::Error(WRONGTYPE.to_string()));
+    }
+    let list = db.get_list_mut(source).unwrap().unwrap();
+    let element = wherefrom.pop(list).unwrap();
+    db.remove_if_empty(source);
+    whereto.push(db.get_or_create_list(destination).unwrap(), element.clone());
+    db.signal_ready(destination);
+    Some(Value::BulkString(element))
+}
+
+pub fn lmove(
+    data: Data,
+    source: &[u8],
+    destination: &[u8],
+    wherefrom: &[u8],
+    whereto: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    let (Some(wherefrom), Some(whereto)) = (Side::parse(wherefrom), Side::parse(whereto)) else {
+        return send_error(client, SYNTAX_ERROR);
+    };
+    let mut lock = data.lock().unwrap();
+    let reply = move_element(&mut lock, source, destination, wherefrom, whereto);
+    client.send(&reply.unwrap_or(Value::Null(NullKind::BulkString)))
+}
+
+/// Pops from the first non empty list among `keys`, replying with the key
+/// and the element.
+fn pop_first(keys: Vec<Vec<u8>>, side: Side) -> Attempt {
+    Box::new(move |db: &mut Db| {
+        for key in &keys {
+            let list = match db.get_list_mut(key) {
+                Ok(Some(list)) => list,
+                Ok(None) => continue,
+                Err(_) => return Some(Value::Error(WRONGTYPE.to_string())),
+            };
+            let element = side.pop(list).unwrap();
+            db.remove_if_empty(key);
+            return Some(Value::Array(vec![
+                Value::BulkString(key.clone()),
+                Value::BulkString(element),
+            ]));
+        }
+        None
+    })
+}
+
+fn blocking_pop(
+    data: Data,
+    key: &[u8],
+    rest: &[Vec<u8>],
+    side: Side,
+    client: &mut Client,
+) -> Result<()> {
+    let (timeout, rest) = rest.split_last().unwrap();
+    let timeout = match parse_timeout(timeout) {
+        Ok(timeout) => timeout,
+        Err(err) => return send_error(client, err),
+    };
+    let keys: Vec<Vec<u8>> = std::iter::once(key.to_vec())
+        .chain(rest.iter().cloned())
+        .collect();
+    let reply = block_on(
+        &data,
+        keys.clone(),
+        timeout,
+        client.peer(),
+        pop_first(keys, side),
+    );
+    let Some(reply) = reply else {
+        return client.send(&Value::Null(NullKind::Array));
+    };
+    let sent = client.send(&reply);
+    // the element would be lost with a reply that didn't make it, so it goes
+    // back where it was popped from
+    if sent.is_err()
+        && let Value::Array(popped) = &reply
+        && let [Value::BulkString(key), Value::BulkString(element)] = popped.as_slice()
+    {
+        let mut lock = data.lock().unwrap();
+        if let Ok(list) = lock.get_or_create_list(key) {
+            side.push(list, element.clone());
+            lock.signal_ready(key);
+        }
+    }
+    sent
+}
+
+/// `BLPOP key [key ...] timeout`
+pub fn blpop(data: Data, key: &[u8], rest: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    blocking_pop(data, key, rest, Side::Left, client)
+}
+
+/// `BRPOP key [key ...] timeout`
+pub fn brpop(data: Data, key: &[u8], rest: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    blocking_pop(data, key, rest, Side::Right, client)
+}
+
+pub fn blmove(
+    data: Data,
+    source: &[u8],
+    destination: &[u8],
+    wherefrom: &[u8],
+    whereto: &[u8],
+    timeout: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    let (Some(wherefrom), Some(whereto)) = (Side::parse(wherefrom), Side::parse(whereto)) else {
+        return send_error(client, SYNTAX_ERROR);
+    };
+    let timeout = match parse_timeout(timeout) {
+        Ok(timeout) => timeout,
+        Err(err) => return send_error(client, err),
+    };
+    let keys = vec![source.to_vec()];
+    let (source, destination) = (source.to_vec(), destination.to_vec());
+    let attempt: Attempt =
+        Box::new(move |db: &mut Db| move_element(db, &source, &destination, wherefrom, whereto));
+    let reply = block_on(&data, keys, timeout, client.peer(), attempt);
+    client.send(&reply.unwrap_or(Value::Null(NullKind::BulkString)))
+}
@@ -1,2 +1,12 @@
-pub mod start_handlers;
+pub mod bitmap_handlers;
 pub mod command_handlers;
+pub mod connection_handlers;
+pub mod geo_handlers;
+pub mod hash_handlers;
+pub mod hyperloglog_handlers;
+pub mod key_handlers;
+pub mod list_handlers;
+pub mod set_handlers;
+pub mod start_handlers;
+pub mod stream_handlers;
+pub mod zset_handlers;
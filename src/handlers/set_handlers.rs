@@ -0,0 +1,443 @@
+use std::{borrow::Cow, collections::HashSet, io::Result, iter::once};
+
+use crate::{
+    Data, NOT_AN_INTEGER, NullKind, SYNTAX_ERROR, Value,
+    client::Client,
+    db::Db,
+    object::{Object, WRONGTYPE, WrongType},
+    scan::{ScanTarget, parse_scan_args},
+    send_error,
+    set::Set,
+    util::{parse_int, random_u64},
+};
+
+/// Above `count` times this many members, SPOP and SRANDMEMBER with a count
+/// draw random members instead of shuffling a copy of the whole set, like
+/// Redis' `SRANDMEMBER_SUB_STRATEGY_MUL`.
+const SRANDMEMBER_SUB_STRATEGY_MUL: usize = 3;
+
+/// `SADD key member [member ...]`
+pub fn sadd(data: Data, key: &[u8], members: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let Ok(set) = lock.get_or_create_set(key) else {
+        return send_error(client, WRONGTYPE);
+    };
+    let added = members
+        .iter()
+        .filter(|member| set.insert(member.to_vec()))
+        .count();
+    client.send(&Value::Integer(added as i64))
+}
+
+/// `SREM key member [member ...]`
+pub fn srem(data: Data, key: &[u8], members: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let set = match lock.get_set_mut(key) {
+        Ok(Some(set)) => set,
+        Ok(None) => return client.send(&Value::Integer(0)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let removed = members.iter().filter(|member| set.remove(member)).count();
+    lock.remove_if_empty(key);
+    client.send(&Value::Integer(removed as i64))
+}
+
+/// Runs `reply` against the set at `key`, an empty one if it doesn't exist.
+fn with_set(
+    data: Data,
+    key: &[u8],
+    client: &mut Client,
+    reply: impl FnOnce(&Set) -> Value,
+) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    match lock.get_set(key) {
+        Ok(Some(set)) => client.send(&reply(set)),
+        Ok(None) => client.send(&reply(&Set::new())),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+pub fn smembers(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    with_set(data, key, client, |set| {
+        Value::Set(
+            set.iter()
+                .map(|member| Value::BulkString(member.into_owned()))
+                .collect(),
+        )
+    })
+}
+
+pub fn sismember(data: Data, key: &[u8], member: &[u8], client: &mut Client) -> Result<()> {
+    with_set(data, key, client, |set| {
+        Value::Integer(set.contains(member) as i64)
+    })
+}
+
+/// `SMISMEMBER key member [member ...]`
+pub fn smismember(data: Data, key: &[u8], members: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    with_set(data, key, client, |set| {
+        Value::Array(
+            members
+                .iter()
+                .map(|member| Value::Integer(set.contains(member) as i64))
+                .collect(),
+        )
+    })
+}
+
+pub fn scard(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    with_set(data, key, client, |set| Value::Integer(set.len() as i64))
+}
+
+/// Up to `count` distinct members of `set`, picked at random.
+///
+/// Like Redis this only looks at the whole set when `count` is close to its
+/// size, otherwise random members are drawn until enough distinct ones came
+/// up.
+fn random_members(set: &Set, count: usize) -> Vec<Vec<u8>> {
+    if count >= set.len() {
+        return set.iter().map(Cow::into_owned).collect();
+    }
+    if count * SRANDMEMBER_SUB_STRATEGY_MUL > set.len() {
+        let mut members: Vec<Vec<u8>> = set.iter().map(Cow::into_owned).collect();
+        // partial Fisher-Yates shuffle, only the first `count` slots matter
+        for i in 0..count {
+            let j = i + (random_u64() % (members.len() - i) as u64) as usize;
+            members.swap(i, j);
+        }
+        members.truncate(count);
+        return members;
+    }
+    let mut picked = HashSet::with_capacity(count);
+    while picked.len() < count {
+        if let Some(member) = set.random() {
+            picked.insert(member.into_owned());
+        }
+    }
+    picked.into_iter().collect()
+}
+
+/// `SPOP key [count]`
+pub fn spop(data: Data, key: &[u8], count: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let count = match count {
+        [] => None,
+        [count] => match parse_int(count) {
+            Some(count) if count >= 0 => Some(count as usize),
+            _ => return send_error(client, "ERR value is out of range, must be positive"),
+        },
+        _ => return send_error(client, SYNTAX_ERROR),
+    };
+    let mut lock = data.lock().unwrap();
+    let set = match lock.get_set_mut(key) {
+        Ok(Some(set)) => set,
+        Ok(None) if count.is_some() => return client.send(&Value::Set(Default::default())),
+        Ok(None) => return client.send(&Value::Null(NullKind::BulkString)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let reply = match count {
+        Some(count) => {
+            let popped = random_members(set, count);
+            for member in &popped {
+                set.remove(member);
+            }
+            Value::Set(popped.into_iter().map(Value::BulkString).collect())
+        }
+        None => Value::BulkString(set.pop_random().unwrap_or_default()),
+    };
+    lock.remove_if_empty(key);
+    client.send(&reply)
+}
+
+/// `SRANDMEMBER key [count]`. A negative count may return the same member
+/// more than once.
+pub fn srandmember(data: Data, key: &[u8], count: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let count = match count {
+        [] => None,
+        [count] => match parse_int(count) {
+            Some(count) if count != i64::MIN => Some(count),
+            Some(_) => return send_error(client, "ERR value is out of range"),
+            None => return send_error(client, NOT_AN_INTEGER),
+        },
+        _ => return send_error(client, SYNTAX_ERROR),
+    };
+    let mut lock = data.lock().unwrap();
+    let set = match lock.get_set(key) {
+        Ok(Some(set)) => set,
+        Ok(None) if count.is_some() => return client.send(&Value::Array(vec![])),
+        Ok(None) => return client.send(&Value::Null(NullKind::BulkString)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let reply = match count {
+        Some(count) if count >= 0 => Value::Array(
+            random_members(set, count as usize)
+                .into_iter()
+                .map(Value::BulkString)
+                .collect(),
+        ),
+        Some(count) => Value::Array(
+            (0..count.unsigned_abs())
+                .filter_map(|_| set.random())
+                .map(|member| Value::BulkString(member.into_owned()))
+                .collect(),
+        ),
+        None => Value::BulkString(set.random().unwrap_or_default().into_owned()),
+    };
+    client.send(&reply)
+}
+
+pub fn smove(
+    data: Data,
+    source: &[u8],
+    destination: &[u8],
+    member: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let is_member = match lock.get_set(source) {
+        Ok(Some(set)) => set.contains(member),
+        Ok(None) => return client.send(&Value::Integer(0)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    if lock.get_set(destination).is_err() {
+        return send_error(client, WRONGTYPE);
+    }
+    if !is_member || source == destination {
+        return client.send(&Value::Integer(is_member as i64));
+    }
+    if let Ok(Some(set)) = lock.get_set_mut(source) {
+        set.remove(member);
+    }
+    lock.remove_if_empty(source);
+    if let Ok(set) = lock.get_or_create_set(destination) {
+        set.insert(member.to_vec());
+    }
+    client.send(&Value::Integer(1))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// Members of the intersection, union or difference of the sets at `keys`,
+/// missing keys counting as empty sets.
+fn set_op(db: &mut Db, keys: &[&[u8]], op: SetOp) -> std::result::Result<Vec<Vec<u8>>, WrongType> {
+    let mut sizes = Vec::with_capacity(keys.len());
+    for key in keys {
+        sizes.push(db.get_set(key)?.map_or(0, Set::len));
+    }
+    let members_of = |db: &mut Db, key: &[u8]| -> Vec<Vec<u8>> {
+        match db.get_set(key) {
+            Ok(Some(set)) => set.iter().map(|member| member.into_owned()).collect(),
+            _ => Vec::new(),
+        }
+    };
+    let result = match op {
+        SetOp::Inter => {
+            if sizes.contains(&0) {
+                return Ok(Vec::new());
+            }
+            // walk the smallest set, checking its members against the others
+            let mut order: Vec<usize> = (0..keys.len()).collect();
+            order.sort_by_key(|&i| sizes[i]);
+            let mut members = members_of(db, keys[order[0]]);
+            for &i in &order[1..] {
+                if let Ok(Some(set)) = db.get_set(keys[i]) {
+                    members.retain(|member| set.contains(member));
+                }
+            }
+            members
+        }
+        SetOp::Union => {
+            let mut members = HashSet::new();
+            for key in keys {
+                members.extend(members_of(db, key));
+            }
+            members.into_iter().collect()
+        }
+        SetOp::Diff => {
+            let mut members = members_of(db, keys[0]);
+            for key in &keys[1..] {
+                if let Ok(Some(set)) = db.get_set(key) {
+                    members.retain(|member| !set.contains(member));
+                }
+            }
+            members
+        }
+    };
+    Ok(result)
+}
+
+fn set_op_command(
+    data: Data,
+    key: &[u8],
+    keys: &[Vec<u8>],
+    op: SetOp,
+    client: &mut Client,
+) -> Result<()> {
+    let keys: Vec<&[u8]> = once(key).chain(keys.iter().map(Vec::as_slice)).collect();
+    let mut lock = data.lock().unwrap();
+    match set_op(&mut lock, &keys, op) {
+        Ok(members) => client.send(&Value::Set(
+            members.into_iter().map(Value::BulkString).collect(),
+        )),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+/// Stores the result of `op` at `destination`, replacing whatever was
+/// there, and replies with its size. An empty result deletes `destination`.
+fn set_op_store(
+    data: Data,
+    destination: &[u8],
+    key: &[u8],
+    keys: &[Vec<u8>],
+    op: SetOp,
+    client: &mut Client,
+) -> Result<()> {
+    let keys: Vec<&[u8]> = once(key).chain(keys.iter().map(Vec::as_slice)).collect();
+    let mut lock = data.lock().unwrap();
+    let Ok(members) = set_op(&mut lock, &keys, op) else {
+        return send_error(client, WRONGTYPE);
+    };
+    let len = members.len();
+    if members.is_empty() {
+        lock.remove(destination);
+    } else {
+        lock.insert(
+            destination.to_vec(),
+            Object::Set(members.into_iter().collect()),
+        );
+    }
+    client.send(&Value::Integer(len as i64))
+}
+
+/// `SINTER key [key ...]`
+pub fn sinter(data: Data, key: &[u8], keys: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    set_op_command(data, key, keys, SetOp::Inter, client)
+}
+
+/// `SUNION key [key ...]`
+pub fn sunion(data: Data, key: &[u8], keys: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    set_op_command(data, key, keys, SetOp::Union, client)
+}
+
+/// `SDIFF key [key ...]`
+pub fn sdiff(data: Data, key: &[u8], keys: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    set_op_command(data, key, keys, SetOp::Diff, client)
+}
+
+/// `SINTERSTORE destination key [key ...]`
+pub fn sinterstore(
+    data: Data,
+    destination: &[u8],
+    key: &[u8],
+    keys: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    set_op_store(data, destination, key, keys, SetOp::Inter, client)
+}
+
+/// `SUNIONSTORE destination key [key ...]`
+pub fn sunionstore(
+    data: Data,
+    destination: &[u8],
+    key: &[u8],
+    keys: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    set_op_store(data, destination, key, keys, SetOp::Union, client)
+}
+
+/// `SDIFFSTORE destination key [key ...]`
+pub fn sdiffstore(
+    data: Data,
+    destination: &[u8],
+    key: &[u8],
+    keys: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    set_op_store(data, destination, key, keys, SetOp::Diff, client)
+}
+
+/// `SINTERCARD numkeys key [key ...] [LIMIT limit]`. A limit of 0 means no
+/// limit.
+pub fn sintercard(data: Data, numkeys: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let numkeys = match parse_int(numkeys) {
+        Some(n) if n > 0 => n as usize,
+        Some(_) => return send_error(client, "ERR numkeys should be greater than 0"),
+        None => return send_error(client, NOT_AN_INTEGER),
+    };
+    if numkeys > args.len() {
+        return send_error(
+            client,
+            "ERR Number of keys can't be greater than number of args",
+        );
+    }
+    let (keys, options) = args.split_at(numkeys);
+    let mut limit = 0;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        if !option.eq_ignore_ascii_case(b"limit") {
+            return send_error(client, SYNTAX_ERROR);
+        }
+        limit = match options.next().map(|l| parse_int(l)) {
+            Some(Some(l)) if l >= 0 => l as usize,
+            Some(Some(_)) => return send_error(client, "ERR LIMIT can't be negative"),
+            Some(None) => return send_error(client, NOT_AN_INTEGER),
+            None => return send_error(client, SYNTAX_ERROR),
+        };
+    }
+    let keys: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+    let mut lock = data.lock().unwrap();
+    let sets = match lock.get_sets(&keys) {
+        Ok(sets) => sets,
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    // a missing key is an empty set, nothing to intersect
+    let Some(mut sets) = sets.into_iter().collect::<Option<Vec<&Set>>>() else {
+        return client.send(&Value::Integer(0));
+    };
+    // walk the smallest set, checking its members against the others, and
+    // stop as soon as the limit is reached
+    sets.sort_by_key(|set| set.len());
+    let (smallest, others) = sets.split_first().unwrap();
+    let mut cardinality = 0;
+    for member in smallest.iter() {
+        if others.iter().all(|set| set.contains(&member)) {
+            cardinality += 1;
+            if cardinality == limit {
+                break;
+            }
+        }
+    }
+    client.send(&Value::Integer(cardinality as i64))
+}
+
+/// `SSCAN key cursor [MATCH pattern] [COUNT count]`
+pub fn sscan(
+    data: Data,
+    key: &[u8],
+    cursor: &[u8],
+    args: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let (cursor, options) = match parse_scan_args(cursor, args, ScanTarget::Set) {
+        Ok(parsed) => parsed,
+        Err(err) => return send_error(client, &err),
+    };
+    with_set(data, key, client, |set| {
+        let mut members = Vec::new();
+        let next = set.scan(cursor, options.count, |member| {
+            if options.matches(&member) {
+                members.push(Value::BulkString(member.into_owned()));
+            }
+        });
+        Value::Array(vec![
+            Value::BulkString(next.to_string().into_bytes()),
+            Value::Array(members),
+        ])
+    })
+}
@@ -1,21 +1,16 @@
-use std::{
-    fs::File,
-    io::{Result, Write},
-};
+use std::{fs::File, io::Result};
 
-use crate::parse::parse;
+use crate::{Value, client::Client, parse::parse};
 
-fn prepare_docs() -> Vec<u8> {
+fn prepare_docs() -> Value {
     let mut file = File::open("resp_docs.txt").expect("ERROR: cannot find file 'resp_docs.tst'");
-    let resp = parse(&mut file).expect("ERROR: cannot parse prepared presponse");
-    resp.to_bytes()
+    parse(&mut file).expect("ERROR: cannot parse prepared presponse")
 }
 
-pub fn handle_command_docs(arg: &str, stream: &mut dyn Write) -> Result<()> {
-    if arg.to_lowercase() == "docs" {
+pub fn handle_command_docs(arg: &[u8], client: &mut Client) -> Result<()> {
+    if arg.eq_ignore_ascii_case(b"docs") {
         let resp = prepare_docs();
-        stream.write_all(&resp)?;
-        stream.flush()?;
+        client.send(&resp)?;
     }
     Ok(())
 }
@@ -0,0 +1,1159 @@
+use std::{io::Result, time::Duration};
+
+use crate::{
+    Data, NOT_AN_INTEGER, NullKind, Protocol, SYNTAX_ERROR, Value,
+    blocking::{Attempt, block_on, parse_timeout_ms},
+    client::{Client, Peer},
+    db::{Db, now_ms},
+    object::{Object, WRONGTYPE},
+    send_error,
+    stream::{
+        ClaimOptions, ConsumerGroup, Fields, INVALID_ID, NewId, STREAM_NODE_MAX_ENTRIES, Stream,
+        StreamId, Trim, TrimStrategy,
+    },
+    util::parse_int,
+};
+
+pub(crate) fn entry_reply(id: StreamId, fields: &Fields) -> Value {
+    Value::Array(vec![
+        Value::BulkString(id.to_bytes()),
+        Value::Array(fields.iter().cloned().map(Value::BulkString).collect()),
+    ])
+}
+
+/// Parses `MAXLEN|MINID [=|~] threshold [LIMIT count]` starting at
+/// `args[*i]`, leaving `*i` right after it.
+fn parse_trim(args: &[Vec<u8>], i: &mut usize) -> std::result::Result<Trim, &'static str> {
+    let maxlen = args[*i].eq_ignore_ascii_case(b"maxlen");
+    *i += 1;
+    let mut approx = false;
+    match args.get(*i).map(Vec::as_slice) {
+        Some(b"~") => {
+            approx = true;
+            *i += 1;
+        }
+        Some(b"=") => *i += 1,
+        _ => {}
+    }
+    let threshold = args.get(*i).ok_or(SYNTAX_ERROR)?;
+    *i += 1;
+    let strategy = if maxlen {
+        match parse_int(threshold) {
+            Some(max) if max >= 0 => TrimStrategy::MaxLen(max as u64),
+            Some(_) => return Err("ERR The MAXLEN argument must be >= 0."),
+            None => return Err(NOT_AN_INTEGER),
+        }
+    } else {
+        TrimStrategy::MinId(StreamId::parse(threshold, 0).ok_or(INVALID_ID)?)
+    };
+    let mut limit = None;
+    if args
+        .get(*i)
+        .is_some_and(|a| a.eq_ignore_ascii_case(b"limit"))
+    {
+        let count = args.get(*i + 1).ok_or(SYNTAX_ERROR)?;
+        *i += 2;
+        limit = match parse_int(count) {
+            Some(count) if count >= 0 => Some(count as u64),
+            Some(_) => return Err("ERR The LIMIT argument must be >= 0."),
+            None => return Err(NOT_AN_INTEGER),
+        };
+        if !approx {
+            return Err("ERR syntax error, LIMIT cannot be used without the special ~ option");
+        }
+    }
+    Ok(Trim {
+        strategy,
+        approx,
+        limit,
+    })
+}
+
+/// `XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]]
+/// *|id field value [field value ...]`
+pub fn xadd(data: Data, key: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let mut nomkstream = false;
+    let mut trim = None;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].to_ascii_lowercase().as_slice() {
+            b"nomkstream" => {
+                nomkstream = true;
+                i += 1;
+            }
+            b"maxlen" | b"minid" => match parse_trim(args, &mut i) {
+                Ok(parsed) => trim = Some(parsed),
+                Err(err) => return send_error(client, err),
+            },
+            _ => break,
+        }
+    }
+    let Some((id, fields)) = args[i..].split_first() else {
+        return send_error(client, SYNTAX_ERROR);
+    };
+    if fields.is_empty() || !fields.len().is_multiple_of(2) {
+        return send_error(client, "ERR wrong number of arguments for 'xadd' command");
+    }
+    let Some(id) = NewId::parse(id) else {
+        return send_error(client, INVALID_ID);
+    };
+
+    let mut lock = data.lock().unwrap();
+    let id = match lock.get_stream(key) {
+        Ok(Some(stream)) => stream.next_id(id),
+        Ok(None) if nomkstream => return client.send(&Value::Null(NullKind::BulkString)),
+        Ok(None) => Stream::new().next_id(id),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let id = match id {
+        Ok(id) => id,
+        Err(err) => return send_error(client, err),
+    };
+    if !lock.contains_key(key) {
+        lock.insert(key.to_vec(), Object::Stream(Stream::new()));
+    }
+    if let Ok(Some(stream)) = lock.get_stream_mut(key) {
+        stream.add(id, fields.to_vec());
+        if let Some(trim) = trim {
+            stream.trim(&trim);
+        }
+    }
+    lock.signal_ready(key);
+    client.send(&Value::BulkString(id.to_bytes()))
+}
+
+pub fn xlen(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    match lock.get_stream(key) {
+        Ok(stream) => client.send(&Value::Integer(stream.map_or(0, Stream::len) as i64)),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+/// Start of an XRANGE interval: `-`, an ID, an incomplete `ms` standing for
+/// `ms-0`, or any of those but `-` after a `(` to exclude it.
+fn parse_range_start(arg: &[u8]) -> std::result::Result<StreamId, &'static str> {
+    match arg {
+        b"-" => Ok(StreamId::MIN),
+        b"+" => Ok(StreamId::MAX),
+        [b'(', id @ ..] => StreamId::parse(id, 0)
+            .ok_or(INVALID_ID)?
+            .next()
+            .ok_or("ERR invalid start ID for the interval"),
+        id => StreamId::parse(id, 0).ok_or(INVALID_ID),
+    }
+}
+
+/// End of an XRANGE interval, where an incomplete `ms` means the last
+/// possible sequence in it.
+fn parse_range_end(arg: &[u8]) -> std::result::Result<StreamId, &'static str> {
+    match arg {
+        b"-" => Ok(StreamId::MIN),
+        b"+" => Ok(StreamId::MAX),
+        [b'(', id @ ..] => StreamId::parse(id, u64::MAX)
+            .ok_or(INVALID_ID)?
+            .prev()
+            .ok_or("ERR invalid end ID for the interval"),
+        id => StreamId::parse(id, u64::MAX).ok_or(INVALID_ID),
+    }
+}
+
+fn range(
+    data: Data,
+    key: &[u8],
+    start: &[u8],
+    end: &[u8],
+    options: &[Vec<u8>],
+    rev: bool,
+    client: &mut Client,
+) -> Result<()> {
+    let bounds = parse_range_start(start).and_then(|s| Ok((s, parse_range_end(end)?)));
+    let (start, end) = match bounds {
+        Ok(bounds) => bounds,
+        Err(err) => return send_error(client, err),
+    };
+    let count = match options {
+        [] => usize::MAX,
+        [option, count] if option.eq_ignore_ascii_case(b"count") => match parse_int(count) {
+            Some(count) => count.max(0) as usize,
+            None => return send_error(client, NOT_AN_INTEGER),
+        },
+        _ => return send_error(client, SYNTAX_ERROR),
+    };
+    let mut lock = data.lock().unwrap();
+    let stream = match lock.get_stream(key) {
+        Ok(Some(stream)) => stream,
+        Ok(None) => return client.send(&Value::Array(Vec::new())),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let entries = stream
+        .range(start, end, rev)
+        .take(count)
+        .map(|(id, fields)| entry_reply(id, fields))
+        .collect();
+    client.send(&Value::Array(entries))
+}
+
+/// `XRANGE key start end [COUNT count]`
+pub fn xrange(
+    data: Data,
+    key: &[u8],
+    start: &[u8],
+    end: &[u8],
+    options: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    range(data, key, start, end, options, false, client)
+}
+
+/// `XREVRANGE key end start [COUNT count]`
+pub fn xrevrange(
+    data: Data,
+    key: &[u8],
+    end: &[u8],
+    start: &[u8],
+    options: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    range(data, key, start, end, options, true, client)
+}
+
+/// `XTRIM key MAXLEN|MINID [=|~] threshold [LIMIT count]`
+pub fn xtrim(data: Data, key: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let is_strategy =
+        |arg: &Vec<u8>| arg.eq_ignore_ascii_case(b"maxlen") || arg.eq_ignore_ascii_case(b"minid");
+    if !is_strategy(&args[0]) {
+        return send_error(client, SYNTAX_ERROR);
+    }
+    let mut i = 0;
+    let trim = match parse_trim(args, &mut i) {
+        Ok(_) if i < args.len() => return send_error(client, SYNTAX_ERROR),
+        Ok(trim) => trim,
+        Err(err) => return send_error(client, err),
+    };
+    let mut lock = data.lock().unwrap();
+    match lock.get_stream_mut(key) {
+        Ok(Some(stream)) => client.send(&Value::Integer(stream.trim(&trim) as i64)),
+        Ok(None) => client.send(&Value::Integer(0)),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+/// `XDEL key id [id ...]`
+pub fn xdel(data: Data, key: &[u8], ids: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let Some(ids) = ids
+        .iter()
+        .map(|id| StreamId::parse(id, 0))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return send_error(client, INVALID_ID);
+    };
+    let mut lock = data.lock().unwrap();
+    match lock.get_stream_mut(key) {
+        Ok(Some(stream)) => {
+            let deleted = ids.into_iter().filter(|id| stream.remove(*id)).count();
+            client.send(&Value::Integer(deleted as i64))
+        }
+        Ok(None) => client.send(&Value::Integer(0)),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+fn id_reply(id: StreamId) -> Value {
+    Value::BulkString(id.to_bytes())
+}
+
+/// An entry that may have been deleted since it was delivered, in which case
+/// it comes with nil fields.
+fn pending_entry_reply(id: StreamId, fields: Option<&Fields>) -> Value {
+    match fields {
+        Some(fields) => entry_reply(id, fields),
+        None => Value::Array(vec![id_reply(id), Value::Null(NullKind::Array)]),
+    }
+}
+
+fn info_reply(fields: Vec<(&str, Value)>) -> Value {
+    Value::Map(
+        fields
+            .into_iter()
+            .map(|(name, value)| (Value::BulkString(name.as_bytes().to_vec()), value))
+            .collect(),
+    )
+}
+
+fn optional_integer(n: Option<u64>) -> Value {
+    n.map_or(Value::Null(NullKind::BulkString), |n| {
+        Value::Integer(n as i64)
+    })
+}
+
+fn no_group(key: &[u8], group: &[u8]) -> String {
+    format!(
+        "NOGROUP No such key '{}' or consumer group '{}'",
+        String::from_utf8_lossy(key),
+        String::from_utf8_lossy(group)
+    )
+}
+
+/// The ID a group starts delivering after: `$` for the end of the stream.
+fn parse_group_id(arg: &[u8], stream: &Stream) -> std::result::Result<StreamId, &'static str> {
+    match arg {
+        b"$" => Ok(stream.last_id()),
+        id => StreamId::parse(id, 0).ok_or(INVALID_ID),
+    }
+}
+
+/// Parses the optional `ENTRIESREAD entries-read` of XGROUP, `-1` meaning
+/// unknown.
+fn parse_entries_read(args: &[Vec<u8>]) -> std::result::Result<Option<u64>, &'static str> {
+    match args {
+        [] => Ok(None),
+        [option, n] if option.eq_ignore_ascii_case(b"entriesread") => match parse_int(n) {
+            Some(-1) => Ok(None),
+            Some(n) if n >= 0 => Ok(Some(n as u64)),
+            Some(_) => Err("ERR value for ENTRIESREAD must be positive or -1"),
+            None => Err(NOT_AN_INTEGER),
+        },
+        _ => Err(SYNTAX_ERROR),
+    }
+}
+
+/// `XGROUP CREATE|SETID|DESTROY|CREATECONSUMER|DELCONSUMER key group ...`
+pub fn xgroup(data: Data, subcommand: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let name = subcommand.to_ascii_lowercase();
+    let arity_ok = match (name.as_slice(), args.len()) {
+        (b"create", n) => (3..=6).contains(&n),
+        (b"setid", n) => n == 3 || n == 5,
+        (b"destroy", n) => n == 2,
+        (b"createconsumer" | b"delconsumer", n) => n == 3,
+        _ => false,
+    };
+    if !arity_ok {
+        let msg = format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try XGROUP HELP.",
+            String::from_utf8_lossy(subcommand)
+        );
+        return send_error(client, &msg);
+    }
+    let (key, group, rest) = (&args[0], &args[1], &args[2..]);
+
+    let mut lock = data.lock().unwrap();
+    if name == b"create" {
+        let mut mkstream = false;
+        let mut options = &rest[1..];
+        if let Some((first, others)) = options.split_first()
+            && first.eq_ignore_ascii_case(b"mkstream")
+        {
+            mkstream = true;
+            options = others;
+        }
+        let entries_read = match parse_entries_read(options) {
+            Ok(entries_read) => entries_read,
+            Err(err) => return send_error(client, err),
+        };
+        if mkstream && !lock.contains_key(key) {
+            lock.insert(key.clone(), Object::Stream(Stream::new()));
+        }
+        let stream = match lock.get_stream_mut(key) {
+            Ok(Some(stream)) => stream,
+            Ok(None) => {
+                return send_error(
+                    client,
+                    "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE \
+                     you may want to use the MKSTREAM option to create an empty stream \
+                     automatically.",
+                );
+            }
+            Err(_) => return send_error(client, WRONGTYPE),
+        };
+        let id = match parse_group_id(&rest[0], stream) {
+            Ok(id) => id,
+            Err(err) => return send_error(client, err),
+        };
+        if !stream.create_group(group, id, entries_read) {
+            return send_error(client, "BUSYGROUP Consumer Group name already exists");
+        }
+        return client.send(&Value::String("OK".to_string()));
+    }
+
+    let stream = match lock.get_stream_mut(key) {
+        Ok(Some(stream)) => stream,
+        Ok(None) => {
+            return send_error(
+                client,
+                "ERR The XGROUP subcommand requires the key to exist.",
+            );
+        }
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    if name == b"destroy" {
+        return client.send(&Value::Integer(stream.destroy_group(group) as i64));
+    }
+    if stream.group(group).is_none() {
+        let msg = format!(
+            "NOGROUP No such consumer group '{}' for key name '{}'",
+            String::from_utf8_lossy(group),
+            String::from_utf8_lossy(key)
+        );
+        return send_error(client, &msg);
+    }
+    match name.as_slice() {
+        b"setid" => {
+            let parsed = parse_group_id(&rest[0], stream)
+                .and_then(|id| Ok((id, parse_entries_read(&rest[1..])?)));
+            let (id, entries_read) = match parsed {
+                Ok(parsed) => parsed,
+                Err(err) => return send_error(client, err),
+            };
+            stream.set_group_id(group, id, entries_read);
+            client.send(&Value::String("OK".to_string()))
+        }
+        b"createconsumer" => {
+            let group = stream.group_mut(group).unwrap();
+            let created = !group.consumers.contains_key(&rest[0]);
+            group.consumer(&rest[0], now_ms());
+            client.send(&Value::Integer(created as i64))
+        }
+        _ => {
+            let group = stream.group_mut(group).unwrap();
+            let pending = group.remove_consumer(&rest[0]).unwrap_or(0);
+            client.send(&Value::Integer(pending as i64))
+        }
+    }
+}
+
+/// Arguments shared by XREAD and XREADGROUP.
+#[derive(Debug, Default)]
+struct ReadOptions {
+    group: Option<(Vec<u8>, Vec<u8>)>,
+    count: usize,
+    block: Option<Option<Duration>>,
+    noack: bool,
+    keys: Vec<Vec<u8>>,
+    ids: Vec<Vec<u8>>,
+}
+
+fn parse_read_options(args: &[Vec<u8>], command: &str) -> std::result::Result<ReadOptions, String> {
+    let mut options = ReadOptions {
+        count: usize::MAX,
+        ..Default::default()
+    };
+    let mut i = 0;
+    while i < args.len() {
+        let option = args[i].to_ascii_lowercase();
+        let value = args.get(i + 1);
+        match (option.as_slice(), value) {
+            (b"streams", _) => {
+                let streams = &args[i + 1..];
+                if streams.is_empty() || !streams.len().is_multiple_of(2) {
+                    return Err(format!(
+                        "ERR Unbalanced '{command}' list of streams: for each stream key an ID \
+                         or '{}' must be specified.",
+                        if options.group.is_some() { ">" } else { "$" }
+                    ));
+                }
+                let (keys, ids) = streams.split_at(streams.len() / 2);
+                options.keys = keys.to_vec();
+                options.ids = ids.to_vec();
+                break;
+            }
+            (b"count", Some(count)) => {
+                options.count = match parse_int(count) {
+                    Some(count) if count > 0 => count as usize,
+                    Some(_) => usize::MAX,
+                    None => return Err(NOT_AN_INTEGER.to_string()),
+                };
+                i += 2;
+            }
+            (b"block", Some(timeout)) => {
+                options.block = Some(parse_timeout_ms(timeout)?);
+                i += 2;
+            }
+            (b"noack", _) if command == "xreadgroup" => {
+                options.noack = true;
+                i += 1;
+            }
+            (b"group", Some(group)) if command == "xreadgroup" => {
+                let consumer = args.get(i + 2).ok_or(SYNTAX_ERROR)?;
+                options.group = Some((group.clone(), consumer.clone()));
+                i += 3;
+            }
+            (b"group", _) if command == "xread" => {
+                return Err(
+                    "ERR The GROUP option is only supported by XREADGROUP. You called \
+                            XREAD instead."
+                        .to_string(),
+                );
+            }
+            _ => return Err(SYNTAX_ERROR.to_string()),
+        }
+    }
+    if options.keys.is_empty() {
+        return Err(SYNTAX_ERROR.to_string());
+    }
+    if command == "xreadgroup" && options.group.is_none() {
+        return Err("ERR Missing GROUP option for XREADGROUP".to_string());
+    }
+    Ok(options)
+}
+
+/// The per-stream replies of XREAD and XREADGROUP: a map from key to
+/// entries for RESP3 clients, `[key, entries]` pairs for RESP2 ones.
+fn streams_reply(streams: Vec<(Vec<u8>, Vec<Value>)>, protocol: Protocol) -> Value {
+    match protocol {
+        Protocol::Resp2 => Value::Array(
+            streams
+                .into_iter()
+                .map(|(key, entries)| {
+                    Value::Array(vec![Value::BulkString(key), Value::Array(entries)])
+                })
+                .collect(),
+        ),
+        Protocol::Resp3 => Value::Map(
+            streams
+                .into_iter()
+                .map(|(key, entries)| (Value::BulkString(key), Value::Array(entries)))
+                .collect(),
+        ),
+    }
+}
+
+/// Runs a read once, or blocks on its keys when BLOCK was given.
+fn serve_read(data: &Data, options: ReadOptions, peer: Peer, mut attempt: Attempt) -> Value {
+    let reply = match options.block {
+        None => attempt(&mut data.lock().unwrap()),
+        Some(timeout) => block_on(data, options.keys, timeout, peer, attempt),
+    };
+    reply.unwrap_or(Value::Null(NullKind::Array))
+}
+
+/// `XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id
+/// [id ...]`
+pub fn xread(data: Data, args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let options = match parse_read_options(args, "xread") {
+        Ok(options) => options,
+        Err(err) => return send_error(client, &err),
+    };
+    // `$` is resolved on the first attempt, while holding the lock
+    let mut after = Vec::with_capacity(options.ids.len());
+    for id in &options.ids {
+        match id.as_slice() {
+            b"$" => after.push(None),
+            id => match StreamId::parse(id, 0) {
+                Some(id) => after.push(Some(id)),
+                None => return send_error(client, INVALID_ID),
+            },
+        }
+    }
+    let keys = options.keys.clone();
+    let count = options.count;
+    let protocol = client.protocol;
+    let attempt: Attempt = Box::new(move |db: &mut Db| {
+        let mut streams = Vec::new();
+        for (key, after) in keys.iter().zip(after.iter_mut()) {
+            let stream = match db.get_stream(key) {
+                Ok(stream) => stream,
+                Err(_) => return Some(Value::Error(WRONGTYPE.to_string())),
+            };
+            let after = *after.get_or_insert(stream.map_or(StreamId::MIN, Stream::last_id));
+            let Some(stream) = stream else { continue };
+            let Some(start) = after.next() else { continue };
+            let entries: Vec<Value> = stream
+                .range(start, StreamId::MAX, false)
+                .take(count)
+                .map(|(id, fields)| entry_reply(id, fields))
+                .collect();
+            if !entries.is_empty() {
+                streams.push((key.clone(), entries));
+            }
+        }
+        (!streams.is_empty()).then(|| streams_reply(streams, protocol))
+    });
+    let reply = serve_read(&data, options, client.peer(), attempt);
+    client.send(&reply)
+}
+
+/// `XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds]
+/// [NOACK] STREAMS key [key ...] id [id ...]`
+pub fn xreadgroup(data: Data, args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let options = match parse_read_options(args, "xreadgroup") {
+        Ok(options) => options,
+        Err(err) => return send_error(client, &err),
+    };
+    // `None` for `>`, the entries never delivered to the group
+    let mut after = Vec::with_capacity(options.ids.len());
+    for id in &options.ids {
+        match id.as_slice() {
+            b">" => after.push(None),
+            id => match StreamId::parse(id, 0) {
+                Some(id) => after.push(Some(id)),
+                None => return send_error(client, INVALID_ID),
+            },
+        }
+    }
+    let (group, consumer) = options.group.clone().unwrap();
+    {
+        let mut lock = data.lock().unwrap();
+        for key in &options.keys {
+            match lock.get_stream(key) {
+                Ok(Some(stream)) if stream.group(&group).is_some() => {}
+                Ok(_) => {
+                    let msg = format!(
+                        "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with \
+                         GROUP option",
+                        String::from_utf8_lossy(key),
+                        String::from_utf8_lossy(&group)
+                    );
+                    return send_error(client, &msg);
+                }
+                Err(_) => return send_error(client, WRONGTYPE),
+            }
+        }
+    }
+    let keys = options.keys.clone();
+    let (count, noack) = (options.count, options.noack);
+    let protocol = client.protocol;
+    let attempt: Attempt = Box::new(move |db: &mut Db| {
+        let mut streams = Vec::new();
+        for (key, after) in keys.iter().zip(&after) {
+            let stream = match db.get_stream_mut(key) {
+                Ok(Some(stream)) => stream,
+                Ok(None) => return Some(Value::Error(no_group(key, &group))),
+                Err(_) => return Some(Value::Error(WRONGTYPE.to_string())),
+            };
+            let entries: Vec<Value> = match after {
+                None => match stream.read_group_new(&group, &consumer, count, noack) {
+                    Some(entries) if entries.is_empty() => continue,
+                    Some(entries) => entries
+                        .iter()
+                        .map(|(id, fields)| entry_reply(*id, fields))
+                        .collect(),
+                    None => return Some(Value::Error(no_group(key, &group))),
+                },
+                // history is always replied with, even when empty
+                Some(after) => match stream.read_group_pending(&group, &consumer, *after, count) {
+                    Some(entries) => entries
+                        .iter()
+                        .map(|(id, fields)| pending_entry_reply(*id, fields.as_ref()))
+                        .collect(),
+                    None => return Some(Value::Error(no_group(key, &group))),
+                },
+            };
+            streams.push((key.clone(), entries));
+        }
+        (!streams.is_empty()).then(|| streams_reply(streams, protocol))
+    });
+    let reply = serve_read(&data, options, client.peer(), attempt);
+    client.send(&reply)
+}
+
+/// `XACK key group id [id ...]`
+pub fn xack(
+    data: Data,
+    key: &[u8],
+    group: &[u8],
+    ids: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let Some(ids) = ids
+        .iter()
+        .map(|id| StreamId::parse(id, 0))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return send_error(client, INVALID_ID);
+    };
+    let mut lock = data.lock().unwrap();
+    let group = match lock.get_stream_mut(key) {
+        Ok(stream) => stream.and_then(|stream| stream.group_mut(group)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let acked = group.map_or(0, |group| {
+        ids.into_iter().filter(|id| group.ack(*id)).count()
+    });
+    client.send(&Value::Integer(acked as i64))
+}
+
+fn pending_summary(group: &ConsumerGroup) -> Value {
+    let (Some(first), Some(last)) = (group.pending.keys().next(), group.pending.keys().last())
+    else {
+        return Value::Array(vec![
+            Value::Integer(0),
+            Value::Null(NullKind::BulkString),
+            Value::Null(NullKind::BulkString),
+            Value::Null(NullKind::Array),
+        ]);
+    };
+    let consumers = group
+        .consumers
+        .iter()
+        .filter(|(_, consumer)| !consumer.pending.is_empty())
+        .map(|(name, consumer)| {
+            Value::Array(vec![
+                Value::BulkString(name.clone()),
+                Value::BulkString(consumer.pending.len().to_string().into_bytes()),
+            ])
+        })
+        .collect();
+    Value::Array(vec![
+        Value::Integer(group.pending.len() as i64),
+        id_reply(*first),
+        id_reply(*last),
+        Value::Array(consumers),
+    ])
+}
+
+/// `XPENDING key group [[IDLE min-idle-time] start end count [consumer]]`
+pub fn xpending(
+    data: Data,
+    key: &[u8],
+    group: &[u8],
+    args: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let (min_idle, args) = match args {
+        [option, idle, rest @ ..] if option.eq_ignore_ascii_case(b"idle") => {
+            match parse_int(idle) {
+                Some(idle) => (idle.max(0) as u64, rest),
+                None => return send_error(client, NOT_AN_INTEGER),
+            }
+        }
+        _ => (0, args),
+    };
+    let extended = match args {
+        [] if min_idle == 0 => None,
+        [start, end, count, consumer @ ..] if consumer.len() <= 1 => {
+            let bounds = parse_range_start(start).and_then(|s| Ok((s, parse_range_end(end)?)));
+            let (start, end) = match bounds {
+                Ok(bounds) => bounds,
+                Err(err) => return send_error(client, err),
+            };
+            let Some(count) = parse_int(count) else {
+                return send_error(client, NOT_AN_INTEGER);
+            };
+            Some((start, end, count.max(0) as usize, consumer.first()))
+        }
+        _ => return send_error(client, SYNTAX_ERROR),
+    };
+
+    let mut lock = data.lock().unwrap();
+    let group = match lock.get_stream(key) {
+        Ok(stream) => match stream.and_then(|stream| stream.group(group)) {
+            Some(group) => group,
+            None => return send_error(client, &no_group(key, group)),
+        },
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let Some((start, end, count, consumer)) = extended else {
+        return client.send(&pending_summary(group));
+    };
+    if start > end {
+        return client.send(&Value::Array(Vec::new()));
+    }
+    let now = now_ms();
+    let entries = group
+        .pending
+        .range(start..=end)
+        .filter(|(_, pending)| consumer.is_none_or(|c| *c == pending.consumer))
+        .filter(|(_, pending)| now.saturating_sub(pending.delivery_time) >= min_idle)
+        .take(count)
+        .map(|(id, pending)| {
+            Value::Array(vec![
+                id_reply(*id),
+                Value::BulkString(pending.consumer.clone()),
+                Value::Integer(now.saturating_sub(pending.delivery_time) as i64),
+                Value::Integer(pending.delivery_count as i64),
+            ])
+        })
+        .collect();
+    client.send(&Value::Array(entries))
+}
+
+fn parse_min_idle(arg: &[u8]) -> Option<u64> {
+    parse_int(arg).map(|idle| idle.max(0) as u64)
+}
+
+/// `XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms]
+/// [TIME unix-time-milliseconds] [RETRYCOUNT count] [FORCE] [JUSTID]
+/// [LASTID lastid]`
+pub fn xclaim(
+    data: Data,
+    key: &[u8],
+    group: &[u8],
+    consumer: &[u8],
+    min_idle: &[u8],
+    args: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let Some(min_idle) = parse_min_idle(min_idle) else {
+        return send_error(client, "ERR Invalid min-idle-time argument for XCLAIM");
+    };
+    let now = now_ms();
+    let mut options = ClaimOptions {
+        min_idle,
+        delivery_time: now,
+        retry_count: None,
+        force: false,
+        justid: false,
+    };
+    let mut ids = Vec::new();
+    let mut i = 0;
+    while let Some(id) = args.get(i).and_then(|id| StreamId::parse(id, 0)) {
+        ids.push(id);
+        i += 1;
+    }
+    if ids.is_empty() {
+        return send_error(client, INVALID_ID);
+    }
+    let mut last_id = None;
+    while i < args.len() {
+        let option = args[i].to_ascii_lowercase();
+        let value = args.get(i + 1);
+        match (option.as_slice(), value) {
+            (b"force", _) => options.force = true,
+            (b"justid", _) => options.justid = true,
+            (b"idle" | b"time" | b"retrycount", Some(value)) => {
+                let Some(value) = parse_int(value) else {
+                    return send_error(client, NOT_AN_INTEGER);
+                };
+                let value = value.max(0) as u64;
+                match option.as_slice() {
+                    b"idle" => options.delivery_time = now.saturating_sub(value),
+                    b"time" => options.delivery_time = value.min(now),
+                    _ => options.retry_count = Some(value),
+                }
+                i += 1;
+            }
+            (b"lastid", Some(id)) => {
+                let Some(id) = StreamId::parse(id, 0) else {
+                    return send_error(client, INVALID_ID);
+                };
+                last_id = Some(id);
+                i += 1;
+            }
+            _ => {
+                let msg = format!(
+                    "ERR Unrecognized XCLAIM option '{}'",
+                    String::from_utf8_lossy(&args[i])
+                );
+                return send_error(client, &msg);
+            }
+        }
+        i += 1;
+    }
+
+    let mut lock = data.lock().unwrap();
+    let stream = match lock.get_stream_mut(key) {
+        Ok(Some(stream)) if stream.group(group).is_some() => stream,
+        Ok(_) => return send_error(client, &no_group(key, group)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let group_state = stream.group_mut(group).unwrap();
+    group_state.consumer(consumer, now);
+    if let Some(last_id) = last_id
+        && last_id > group_state.last_delivered
+    {
+        group_state.last_delivered = last_id;
+    }
+    let mut claimed = Vec::new();
+    for id in ids {
+        if let Ok(Some(fields)) = stream.claim(group, consumer, id, &options, now) {
+            claimed.push(if options.justid {
+                id_reply(id)
+            } else {
+                entry_reply(id, &fields)
+            });
+        }
+    }
+    client.send(&Value::Array(claimed))
+}
+
+/// `XAUTOCLAIM key group consumer min-idle-time start [COUNT count]
+/// [JUSTID]`
+pub fn xautoclaim(
+    data: Data,
+    key: &[u8],
+    group: &[u8],
+    consumer: &[u8],
+    min_idle: &[u8],
+    args: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let (start, args) = args.split_first().unwrap();
+    let Some(min_idle) = parse_min_idle(min_idle) else {
+        return send_error(client, "ERR Invalid min-idle-time argument for XAUTOCLAIM");
+    };
+    let start = match parse_range_start(start) {
+        Ok(start) => start,
+        Err(err) => return send_error(client, err),
+    };
+    let mut count = 100;
+    let mut justid = false;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].to_ascii_lowercase().as_slice() {
+            b"justid" => justid = true,
+            b"count" if i + 1 < args.len() => {
+                count = match parse_int(&args[i + 1]) {
+                    Some(count) if count > 0 && count <= i64::MAX / 10 => count as usize,
+                    Some(_) => return send_error(client, "ERR COUNT must be > 0"),
+                    None => return send_error(client, NOT_AN_INTEGER),
+                };
+                i += 1;
+            }
+            _ => return send_error(client, SYNTAX_ERROR),
+        }
+        i += 1;
+    }
+
+    let now = now_ms();
+    let options = ClaimOptions {
+        min_idle,
+        delivery_time: now,
+        retry_count: None,
+        force: false,
+        justid,
+    };
+    let mut lock = data.lock().unwrap();
+    let stream = match lock.get_stream_mut(key) {
+        Ok(Some(stream)) if stream.group(group).is_some() => stream,
+        Ok(_) => return send_error(client, &no_group(key, group)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let group_state = stream.group_mut(group).unwrap();
+    group_state.consumer(consumer, now);
+    // every pending entry looked at is an attempt, claimed or not
+    let attempts = count * 10;
+    let scanned: Vec<StreamId> = group_state
+        .pending
+        .range(start..)
+        .take(attempts + 1)
+        .map(|(id, _)| *id)
+        .collect();
+    let mut claimed = Vec::new();
+    let mut deleted = Vec::new();
+    let mut next = 0;
+    while next < scanned.len().min(attempts) && claimed.len() < count {
+        let id = scanned[next];
+        match stream.claim(group, consumer, id, &options, now) {
+            Ok(Some(_)) if justid => claimed.push(id_reply(id)),
+            Ok(Some(fields)) => claimed.push(entry_reply(id, &fields)),
+            Ok(None) => {}
+            Err(id) => deleted.push(id_reply(id)),
+        }
+        next += 1;
+    }
+    let cursor = scanned.get(next).copied().unwrap_or(StreamId::MIN);
+    client.send(&Value::Array(vec![
+        id_reply(cursor),
+        Value::Array(claimed),
+        Value::Array(deleted),
+    ]))
+}
+
+/// Approximate size of the radix tree of listpacks Redis would keep the
+/// stream in, reported by XINFO STREAM for compatibility.
+fn radix_tree_keys(stream: &Stream) -> usize {
+    stream.len().div_ceil(STREAM_NODE_MAX_ENTRIES as usize)
+}
+
+fn stream_info(stream: &Stream) -> Vec<(&'static str, Value)> {
+    let first_id = stream.first_entry().map_or(StreamId::MIN, |(id, _)| id);
+    vec![
+        ("length", Value::Integer(stream.len() as i64)),
+        (
+            "radix-tree-keys",
+            Value::Integer(radix_tree_keys(stream) as i64),
+        ),
+        (
+            "radix-tree-nodes",
+            Value::Integer(radix_tree_keys(stream) as i64 + 1),
+        ),
+        ("last-generated-id", id_reply(stream.last_id())),
+        ("max-deleted-entry-id", id_reply(stream.max_deleted_id())),
+        (
+            "entries-added",
+            Value::Integer(stream.entries_added() as i64),
+        ),
+        ("recorded-first-entry-id", id_reply(first_id)),
+    ]
+}
+
+fn stream_info_full(stream: &Stream, count: usize) -> Value {
+    let mut info = stream_info(stream);
+    let entries = stream
+        .range(StreamId::MIN, StreamId::MAX, false)
+        .take(count)
+        .map(|(id, fields)| entry_reply(id, fields))
+        .collect();
+    info.push(("entries", Value::Array(entries)));
+    let groups = stream
+        .groups()
+        .iter()
+        .map(|(name, group)| {
+            let pending = group
+                .pending
+                .iter()
+                .take(count)
+                .map(|(id, pending)| {
+                    Value::Array(vec![
+                        id_reply(*id),
+                        Value::BulkString(pending.consumer.clone()),
+                        Value::Integer(pending.delivery_time as i64),
+                        Value::Integer(pending.delivery_count as i64),
+                    ])
+                })
+                .collect();
+            let consumers = group
+                .consumers
+                .iter()
+                .map(|(name, consumer)| {
+                    let pending = consumer
+                        .pending
+                        .iter()
+                        .take(count)
+                        .map(|id| {
+                            let pending = &group.pending[id];
+                            Value::Array(vec![
+                                id_reply(*id),
+                                Value::Integer(pending.delivery_time as i64),
+                                Value::Integer(pending.delivery_count as i64),
+                            ])
+                        })
+                        .collect();
+                    info_reply(vec![
+                        ("name", Value::BulkString(name.clone())),
+                        ("seen-time", Value::Integer(consumer.seen_time as i64)),
+                        (
+                            "active-time",
+                            Value::Integer(consumer.active_time.map_or(-1, |t| t as i64)),
+                        ),
+                        ("pel-count", Value::Integer(consumer.pending.len() as i64)),
+                        ("pending", Value::Array(pending)),
+                    ])
+                })
+                .collect();
+            info_reply(vec![
+                ("name", Value::BulkString(name.clone())),
+                ("last-delivered-id", id_reply(group.last_delivered)),
+                ("entries-read", optional_integer(group.entries_read)),
+                ("lag", optional_integer(stream.lag(group))),
+                ("pel-count", Value::Integer(group.pending.len() as i64)),
+                ("pending", Value::Array(pending)),
+                ("consumers", Value::Array(consumers)),
+            ])
+        })
+        .collect();
+    info.push(("groups", Value::Array(groups)));
+    info_reply(info)
+}
+
+fn group_info(stream: &Stream, name: &[u8], group: &ConsumerGroup) -> Value {
+    info_reply(vec![
+        ("name", Value::BulkString(name.to_vec())),
+        ("consumers", Value::Integer(group.consumers.len() as i64)),
+        ("pending", Value::Integer(group.pending.len() as i64)),
+        ("last-delivered-id", id_reply(group.last_delivered)),
+        ("entries-read", optional_integer(group.entries_read)),
+        ("lag", optional_integer(stream.lag(group))),
+    ])
+}
+
+/// `XINFO STREAM key [FULL [COUNT count]]`, `XINFO GROUPS key` or
+/// `XINFO CONSUMERS key group`
+pub fn xinfo(data: Data, subcommand: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let name = subcommand.to_ascii_lowercase();
+    let arity_ok = match (name.as_slice(), args.len()) {
+        (b"stream", n) => (1..=4).contains(&n),
+        (b"groups", n) => n == 1,
+        (b"consumers", n) => n == 2,
+        _ => false,
+    };
+    if !arity_ok {
+        let msg = format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try XINFO HELP.",
+            String::from_utf8_lossy(subcommand)
+        );
+        return send_error(client, &msg);
+    }
+    let key = &args[0];
+    // `None` when not FULL, otherwise how many entries to show
+    let full = match &args[1..] {
+        [] => None,
+        [full] if full.eq_ignore_ascii_case(b"full") => Some(10),
+        [full, option, count]
+            if full.eq_ignore_ascii_case(b"full") && option.eq_ignore_ascii_case(b"count") =>
+        {
+            match parse_int(count) {
+                Some(count) if count > 0 => Some(count as usize),
+                Some(_) => Some(usize::MAX),
+                None => return send_error(client, NOT_AN_INTEGER),
+            }
+        }
+        _ if name == b"consumers" => None,
+        _ => return send_error(client, SYNTAX_ERROR),
+    };
+
+    let mut lock = data.lock().unwrap();
+    let stream = match lock.get_stream(key) {
+        Ok(Some(stream)) => stream,
+        Ok(None) => return send_error(client, "ERR no such key"),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    match name.as_slice() {
+        b"stream" => {
+            if let Some(count) = full {
+                return client.send(&stream_info_full(stream, count));
+            }
+            let mut info = stream_info(stream);
+            info.push(("groups", Value::Integer(stream.groups().len() as i64)));
+            for (name, entry) in [
+                ("first-entry", stream.first_entry()),
+                ("last-entry", stream.last_entry()),
+            ] {
+                let entry = entry.map_or(Value::Null(NullKind::Array), |(id, fields)| {
+                    entry_reply(id, fields)
+                });
+                info.push((name, entry));
+            }
+            client.send(&info_reply(info))
+        }
+        b"groups" => {
+            let groups = stream
+                .groups()
+                .iter()
+                .map(|(name, group)| group_info(stream, name, group))
+                .collect();
+            client.send(&Value::Array(groups))
+        }
+        _ => {
+            let Some(group) = stream.group(&args[1]) else {
+                let msg = format!(
+                    "NOGROUP No such consumer group '{}' for key name '{}'",
+                    String::from_utf8_lossy(&args[1]),
+                    String::from_utf8_lossy(key)
+                );
+                return send_error(client, &msg);
+            };
+            let now = now_ms();
+            let consumers = group
+                .consumers
+                .iter()
+                .map(|(name, consumer)| {
+                    let inactive = consumer
+                        .active_time
+                        .map_or(-1, |t| now.saturating_sub(t) as i64);
+                    info_reply(vec![
+                        ("name", Value::BulkString(name.clone())),
+                        ("pending", Value::Integer(consumer.pending.len() as i64)),
+                        (
+                            "idle",
+                            Value::Integer(now.saturating_sub(consumer.seen_time) as i64),
+                        ),
+                        ("inactive", Value::Integer(inactive)),
+                    ])
+                })
+                .collect();
+            client.send(&Value::Array(consumers))
+        }
+    }
+}
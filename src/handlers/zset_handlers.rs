@@ -0,0 +1,865 @@
+use std::{collections::HashMap, io::Result, iter::once, ops::Range};
+
+use crate::{
+    Data, MyFloat, NOT_A_FLOAT, NOT_AN_INTEGER, NullKind, Protocol, SYNTAX_ERROR, Value,
+    blocking::{Attempt, block_on, parse_timeout},
+    client::Client,
+    db::Db,
+    object::{Object, WRONGTYPE, WrongType},
+    scan::{ScanTarget, parse_scan_args},
+    send_error,
+    util::{normalize_range, parse_float, parse_int},
+    zset::{LexRange, ScoreRange, SortedSet},
+};
+
+const NAN_SCORE: &str = "ERR resulting score is not a number (NaN)";
+
+fn score_reply(score: f64) -> Value {
+    Value::Double(MyFloat::Real(score))
+}
+
+/// Elements of a range reply. Scores come as `[member, score]` pairs to
+/// RESP3 clients and interleaved with the members to RESP2 ones.
+fn elements_reply<'a>(
+    elements: impl Iterator<Item = (&'a [u8], f64)>,
+    withscores: bool,
+    protocol: Protocol,
+) -> Value {
+    let mut reply = Vec::new();
+    for (member, score) in elements {
+        let member = Value::BulkString(member.to_vec());
+        match (withscores, protocol) {
+            (false, _) => reply.push(member),
+            (true, Protocol::Resp2) => reply.extend([member, score_reply(score)]),
+            (true, Protocol::Resp3) => reply.push(Value::Array(vec![member, score_reply(score)])),
+        }
+    }
+    Value::Array(reply)
+}
+
+#[derive(Debug, Default)]
+struct ZaddOptions {
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+    ch: bool,
+    incr: bool,
+}
+
+/// `ZADD key [NX|XX] [GT|LT] [CH] [INCR] score member [score member ...]`
+pub fn zadd(data: Data, key: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let mut options = ZaddOptions::default();
+    let mut flags = 0;
+    for arg in args {
+        match arg.to_ascii_lowercase().as_slice() {
+            b"nx" => options.nx = true,
+            b"xx" => options.xx = true,
+            b"gt" => options.gt = true,
+            b"lt" => options.lt = true,
+            b"ch" => options.ch = true,
+            b"incr" => options.incr = true,
+            _ => break,
+        }
+        flags += 1;
+    }
+    let pairs = &args[flags..];
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return send_error(client, SYNTAX_ERROR);
+    }
+    if options.nx && options.xx {
+        return send_error(
+            client,
+            "ERR XX and NX options at the same time are not compatible",
+        );
+    }
+    if (options.gt && options.lt) || (options.nx && (options.gt || options.lt)) {
+        return send_error(
+            client,
+            "ERR GT, LT, and/or NX options at the same time are not compatible",
+        );
+    }
+    if options.incr && pairs.len() > 2 {
+        return send_error(
+            client,
+            "ERR INCR option supports a single increment-element pair",
+        );
+    }
+    let mut elements = Vec::with_capacity(pairs.len() / 2);
+    for pair in pairs.chunks(2) {
+        let Some(score) = parse_float(&pair[0]) else {
+            return send_error(client, NOT_A_FLOAT);
+        };
+        elements.push((score, &pair[1]));
+    }
+
+    let mut lock = data.lock().unwrap();
+    let Ok(zset) = lock.get_or_create_zset(key) else {
+        return send_error(client, WRONGTYPE);
+    };
+    let (mut added, mut changed) = (0, 0);
+    let mut incr_result = None;
+    for (score, member) in elements {
+        match zset.score(member) {
+            Some(current) => {
+                if options.nx {
+                    continue;
+                }
+                let new = if options.incr { current + score } else { score };
+                if new.is_nan() {
+                    lock.remove_if_empty(key);
+                    return send_error(client, NAN_SCORE);
+                }
+                if (options.gt && new <= current) || (options.lt && new >= current) {
+                    continue;
+                }
+                if new != current {
+                    zset.insert(member.clone(), new);
+                    changed += 1;
+                }
+                incr_result = Some(new);
+            }
+            None => {
+                if options.xx {
+                    continue;
+                }
+                zset.insert(member.clone(), score);
+                added += 1;
+                incr_result = Some(score);
+            }
+        }
+    }
+    lock.remove_if_empty(key);
+    lock.signal_ready(key);
+    if options.incr {
+        return client.send(&incr_result.map_or(Value::Null(NullKind::BulkString), score_reply));
+    }
+    let count = if options.ch { added + changed } else { added };
+    client.send(&Value::Integer(count))
+}
+
+pub fn zincrby(
+    data: Data,
+    key: &[u8],
+    increment: &[u8],
+    member: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    let Some(increment) = parse_float(increment) else {
+        return send_error(client, NOT_A_FLOAT);
+    };
+    let mut lock = data.lock().unwrap();
+    let Ok(zset) = lock.get_or_create_zset(key) else {
+        return send_error(client, WRONGTYPE);
+    };
+    let new = zset.score(member).unwrap_or(0.0) + increment;
+    if new.is_nan() {
+        lock.remove_if_empty(key);
+        return send_error(client, NAN_SCORE);
+    }
+    zset.insert(member.to_vec(), new);
+    lock.signal_ready(key);
+    client.send(&score_reply(new))
+}
+
+/// `ZREM key member [member ...]`
+pub fn zrem(data: Data, key: &[u8], members: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let zset = match lock.get_zset_mut(key) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => return client.send(&Value::Integer(0)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let removed = members
+        .iter()
+        .filter(|member| zset.remove(member).is_some())
+        .count();
+    lock.remove_if_empty(key);
+    client.send(&Value::Integer(removed as i64))
+}
+
+/// Runs `reply` against the sorted set at `key`, an empty one if it doesn't
+/// exist.
+fn with_zset(
+    data: Data,
+    key: &[u8],
+    client: &mut Client,
+    reply: impl FnOnce(&SortedSet, Protocol) -> Value,
+) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let protocol = client.protocol;
+    match lock.get_zset(key) {
+        Ok(Some(zset)) => client.send(&reply(zset, protocol)),
+        Ok(None) => client.send(&reply(&SortedSet::new(), protocol)),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+pub fn zcard(data: Data, key: &[u8], client: &mut Client) -> Result<()> {
+    with_zset(data, key, client, |zset, _| {
+        Value::Integer(zset.len() as i64)
+    })
+}
+
+pub fn zscore(data: Data, key: &[u8], member: &[u8], client: &mut Client) -> Result<()> {
+    with_zset(data, key, client, |zset, _| {
+        zset.score(member)
+            .map_or(Value::Null(NullKind::BulkString), score_reply)
+    })
+}
+
+fn rank(
+    data: Data,
+    key: &[u8],
+    member: &[u8],
+    options: &[Vec<u8>],
+    rev: bool,
+    client: &mut Client,
+) -> Result<()> {
+    let withscore = match options {
+        [] => false,
+        [option] if option.eq_ignore_ascii_case(b"withscore") => true,
+        _ => return send_error(client, SYNTAX_ERROR),
+    };
+    with_zset(data, key, client, |zset, _| {
+        let Some(rank) = zset.rank(member) else {
+            return match withscore {
+                true => Value::Null(NullKind::Array),
+                false => Value::Null(NullKind::BulkString),
+            };
+        };
+        let rank = if rev { zset.len() - 1 - rank } else { rank };
+        let rank = Value::Integer(rank as i64);
+        match zset.score(member) {
+            Some(score) if withscore => Value::Array(vec![rank, score_reply(score)]),
+            _ => rank,
+        }
+    })
+}
+
+/// `ZRANK key member [WITHSCORE]`
+pub fn zrank(
+    data: Data,
+    key: &[u8],
+    member: &[u8],
+    options: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    rank(data, key, member, options, false, client)
+}
+
+/// `ZREVRANK key member [WITHSCORE]`
+pub fn zrevrank(
+    data: Data,
+    key: &[u8],
+    member: &[u8],
+    options: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    rank(data, key, member, options, true, client)
+}
+
+/// How the bounds of a ZRANGE are to be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RangeBy {
+    Rank,
+    Score,
+    Lex,
+}
+
+/// Bounds of a range, already parsed the way its command asked for.
+#[derive(Debug, Clone, PartialEq)]
+enum RangeSpec {
+    Rank(i64, i64),
+    Score(ScoreRange),
+    Lex(LexRange),
+}
+
+impl RangeSpec {
+    /// Ascending ranks of the elements of `zset` within the bounds. With
+    /// `rev` rank bounds count from the highest score.
+    fn ranks(&self, zset: &SortedSet, rev: bool) -> Range<usize> {
+        match self {
+            RangeSpec::Rank(start, stop) => {
+                let len = zset.len();
+                match normalize_range(*start, *stop, len) {
+                    Some((start, stop)) if rev => len - 1 - stop..len - start,
+                    Some((start, stop)) => start..stop + 1,
+                    None => 0..0,
+                }
+            }
+            RangeSpec::Score(range) => zset.score_range(range),
+            RangeSpec::Lex(range) => zset.lex_range(range),
+        }
+    }
+}
+
+/// Walks the ascending `ranks` of `zset`, from the top if `rev` is set,
+/// skipping `offset` elements and stopping after `limit` of them.
+fn walk_range(
+    zset: &SortedSet,
+    ranks: Range<usize>,
+    rev: bool,
+    offset: usize,
+    limit: Option<usize>,
+) -> impl Iterator<Item = (&[u8], f64)> {
+    let len = ranks.len().saturating_sub(offset);
+    let first = match rev {
+        _ if len == 0 => 0,
+        true => ranks.end - 1 - offset,
+        false => ranks.start + offset,
+    };
+    zset.iter_from(first, rev)
+        .take(len.min(limit.unwrap_or(usize::MAX)))
+}
+
+/// `ZRANGE key start stop [BYSCORE|BYLEX] [REV] [LIMIT offset count]
+/// [WITHSCORES]`
+pub fn zrange(
+    data: Data,
+    key: &[u8],
+    start: &[u8],
+    stop: &[u8],
+    options: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let mut by = RangeBy::Rank;
+    let (mut rev, mut withscores) = (false, false);
+    let mut limit: Option<(i64, i64)> = None;
+    let mut args = options.iter();
+    while let Some(arg) = args.next() {
+        match arg.to_ascii_lowercase().as_slice() {
+            b"byscore" => by = RangeBy::Score,
+            b"bylex" => by = RangeBy::Lex,
+            b"rev" => rev = true,
+            b"withscores" => withscores = true,
+            b"limit" => {
+                let (Some(offset), Some(count)) = (args.next(), args.next()) else {
+                    return send_error(client, SYNTAX_ERROR);
+                };
+                let (Some(offset), Some(count)) = (parse_int(offset), parse_int(count)) else {
+                    return send_error(client, NOT_AN_INTEGER);
+                };
+                limit = Some((offset, count));
+            }
+            _ => return send_error(client, SYNTAX_ERROR),
+        }
+    }
+    if limit.is_some() && by == RangeBy::Rank {
+        return send_error(
+            client,
+            "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX",
+        );
+    }
+    if withscores && by == RangeBy::Lex {
+        return send_error(
+            client,
+            "ERR syntax error, WITHSCORES not supported in combination with BYLEX",
+        );
+    }
+    // with REV the score and lex bounds come highest first
+    let (min, max) = if rev { (stop, start) } else { (start, stop) };
+    let spec = match by {
+        RangeBy::Rank => {
+            let (Some(start), Some(stop)) = (parse_int(start), parse_int(stop)) else {
+                return send_error(client, NOT_AN_INTEGER);
+            };
+            RangeSpec::Rank(start, stop)
+        }
+        RangeBy::Score => match ScoreRange::parse(min, max) {
+            Ok(range) => RangeSpec::Score(range),
+            Err(err) => return send_error(client, err),
+        },
+        RangeBy::Lex => match LexRange::parse(min, max) {
+            Ok(range) => RangeSpec::Lex(range),
+            Err(err) => return send_error(client, err),
+        },
+    };
+    let (offset, limit) = match limit {
+        None => (0, None),
+        Some((offset, _)) if offset < 0 => return client.send(&Value::Array(Vec::new())),
+        Some((offset, count)) => (offset as usize, usize::try_from(count).ok()),
+    };
+    with_zset(data, key, client, |zset, protocol| {
+        elements_reply(
+            walk_range(zset, spec.ranks(zset, rev), rev, offset, limit),
+            withscores,
+            protocol,
+        )
+    })
+}
+
+fn remove_range(data: Data, key: &[u8], spec: RangeSpec, client: &mut Client) -> Result<()> {
+    let mut lock = data.lock().unwrap();
+    let zset = match lock.get_zset_mut(key) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => return client.send(&Value::Integer(0)),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let ranks = spec.ranks(zset, false);
+    let removed = zset.remove_range(ranks);
+    lock.remove_if_empty(key);
+    client.send(&Value::Integer(removed as i64))
+}
+
+pub fn zremrangebyrank(
+    data: Data,
+    key: &[u8],
+    start: &[u8],
+    stop: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    let (Some(start), Some(stop)) = (parse_int(start), parse_int(stop)) else {
+        return send_error(client, NOT_AN_INTEGER);
+    };
+    remove_range(data, key, RangeSpec::Rank(start, stop), client)
+}
+
+pub fn zremrangebyscore(
+    data: Data,
+    key: &[u8],
+    min: &[u8],
+    max: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    match ScoreRange::parse(min, max) {
+        Ok(range) => remove_range(data, key, RangeSpec::Score(range), client),
+        Err(err) => send_error(client, err),
+    }
+}
+
+pub fn zremrangebylex(
+    data: Data,
+    key: &[u8],
+    min: &[u8],
+    max: &[u8],
+    client: &mut Client,
+) -> Result<()> {
+    match LexRange::parse(min, max) {
+        Ok(range) => remove_range(data, key, RangeSpec::Lex(range), client),
+        Err(err) => send_error(client, err),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ZSetOp {
+    Union,
+    Inter,
+    Diff,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            // inf + -inf is taken as 0, like Redis does
+            Aggregate::Sum => Some(a + b).filter(|s| !s.is_nan()).unwrap_or(0.0),
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+/// Parsed arguments of ZUNION, ZINTER, ZDIFF and their STORE variants.
+#[derive(Debug)]
+struct ZSetOpArgs<'a> {
+    keys: &'a [Vec<u8>],
+    weights: Vec<f64>,
+    aggregate: Aggregate,
+    withscores: bool,
+}
+
+/// Parses `numkeys key [key ...] [WEIGHTS weight ...] [AGGREGATE SUM|MIN|MAX]
+/// [WITHSCORES]`. ZDIFF takes neither WEIGHTS nor AGGREGATE, and the STORE
+/// variants take no WITHSCORES.
+fn parse_zset_op_args<'a>(
+    numkeys: &[u8],
+    args: &'a [Vec<u8>],
+    op: ZSetOp,
+    store: bool,
+    command: &str,
+) -> std::result::Result<ZSetOpArgs<'a>, String> {
+    let numkeys = match parse_int(numkeys) {
+        Some(n) if n > 0 => n as usize,
+        Some(_) => {
+            return Err(format!(
+                "ERR at least 1 input key is needed for '{command}' command"
+            ));
+        }
+        None => return Err(NOT_AN_INTEGER.to_string()),
+    };
+    if numkeys > args.len() {
+        return Err(SYNTAX_ERROR.to_string());
+    }
+    let (keys, options) = args.split_at(numkeys);
+    let mut parsed = ZSetOpArgs {
+        keys,
+        weights: vec![1.0; numkeys],
+        aggregate: Aggregate::Sum,
+        withscores: false,
+    };
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.to_ascii_lowercase().as_slice() {
+            b"weights" if op != ZSetOp::Diff => {
+                for weight in parsed.weights.iter_mut() {
+                    let Some(arg) = options.next() else {
+                        return Err(SYNTAX_ERROR.to_string());
+                    };
+                    *weight = parse_float(arg)
+                        .ok_or_else(|| "ERR weight value is not a float".to_string())?;
+                }
+            }
+            b"aggregate" if op != ZSetOp::Diff => {
+                parsed.aggregate = match options.next().map(|a| a.to_ascii_lowercase()) {
+                    Some(a) if a == b"sum" => Aggregate::Sum,
+                    Some(a) if a == b"min" => Aggregate::Min,
+                    Some(a) if a == b"max" => Aggregate::Max,
+                    _ => return Err(SYNTAX_ERROR.to_string()),
+                };
+            }
+            b"withscores" if !store => parsed.withscores = true,
+            _ => return Err(SYNTAX_ERROR.to_string()),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Elements of the sorted set, or plain set whose members all score 1, at
+/// `key`.
+fn scored_members(db: &mut Db, key: &[u8]) -> std::result::Result<Vec<(Vec<u8>, f64)>, WrongType> {
+    match db.get(key) {
+        None => Ok(Vec::new()),
+        Some(Object::ZSet(zset)) => Ok(zset.iter().map(|(m, s)| (m.to_vec(), s)).collect()),
+        Some(Object::Set(set)) => Ok(set.iter().map(|m| (m.into_owned(), 1.0)).collect()),
+        Some(_) => Err(WrongType),
+    }
+}
+
+fn zset_op(
+    db: &mut Db,
+    args: &ZSetOpArgs,
+    op: ZSetOp,
+) -> std::result::Result<SortedSet, WrongType> {
+    let mut inputs = Vec::with_capacity(args.keys.len());
+    for key in args.keys {
+        inputs.push(scored_members(db, key)?);
+    }
+    let weighted = |score: f64, weight: f64| {
+        let score = score * weight;
+        if score.is_nan() { 0.0 } else { score }
+    };
+    let mut result: HashMap<Vec<u8>, f64> = HashMap::new();
+    let mut inputs = inputs.into_iter().zip(&args.weights);
+    if let Some((first, &weight)) = inputs.next() {
+        result.extend(first.into_iter().map(|(m, s)| (m, weighted(s, weight))));
+    }
+    for (input, &weight) in inputs {
+        match op {
+            ZSetOp::Union => {
+                for (member, score) in input {
+                    let score = weighted(score, weight);
+                    result
+                        .entry(member)
+                        .and_modify(|s| *s = args.aggregate.apply(*s, score))
+                        .or_insert(score);
+                }
+            }
+            ZSetOp::Inter => {
+                let input: HashMap<Vec<u8>, f64> = input.into_iter().collect();
+                result.retain(|member, s| match input.get(member) {
+                    Some(&score) => {
+                        *s = args.aggregate.apply(*s, weighted(score, weight));
+                        true
+                    }
+                    None => false,
+                });
+            }
+            ZSetOp::Diff => {
+                for (member, _) in input {
+                    result.remove(&member);
+                }
+            }
+        }
+    }
+    let mut zset = SortedSet::new();
+    for (member, score) in result {
+        zset.insert(member, score);
+    }
+    Ok(zset)
+}
+
+fn zset_op_command(
+    data: Data,
+    numkeys: &[u8],
+    args: &[Vec<u8>],
+    op: ZSetOp,
+    command: &str,
+    client: &mut Client,
+) -> Result<()> {
+    let args = match parse_zset_op_args(numkeys, args, op, false, command) {
+        Ok(args) => args,
+        Err(err) => return send_error(client, &err),
+    };
+    let mut lock = data.lock().unwrap();
+    match zset_op(&mut lock, &args, op) {
+        Ok(zset) => client.send(&elements_reply(
+            zset.iter(),
+            args.withscores,
+            client.protocol,
+        )),
+        Err(_) => send_error(client, WRONGTYPE),
+    }
+}
+
+fn zset_op_store(
+    data: Data,
+    destination: &[u8],
+    numkeys: &[u8],
+    args: &[Vec<u8>],
+    op: ZSetOp,
+    command: &str,
+    client: &mut Client,
+) -> Result<()> {
+    let args = match parse_zset_op_args(numkeys, args, op, true, command) {
+        Ok(args) => args,
+        Err(err) => return send_error(client, &err),
+    };
+    let mut lock = data.lock().unwrap();
+    let Ok(zset) = zset_op(&mut lock, &args, op) else {
+        return send_error(client, WRONGTYPE);
+    };
+    let len = zset.len();
+    if zset.is_empty() {
+        lock.remove(destination);
+    } else {
+        lock.insert(destination.to_vec(), Object::ZSet(zset));
+    }
+    client.send(&Value::Integer(len as i64))
+}
+
+/// `ZUNION numkeys key [key ...] [WEIGHTS weight ...] [AGGREGATE SUM|MIN|MAX]
+/// [WITHSCORES]`
+pub fn zunion(data: Data, numkeys: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    zset_op_command(data, numkeys, args, ZSetOp::Union, "zunion", client)
+}
+
+/// `ZINTER numkeys key [key ...] [WEIGHTS weight ...] [AGGREGATE SUM|MIN|MAX]
+/// [WITHSCORES]`
+pub fn zinter(data: Data, numkeys: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    zset_op_command(data, numkeys, args, ZSetOp::Inter, "zinter", client)
+}
+
+/// `ZDIFF numkeys key [key ...] [WITHSCORES]`
+pub fn zdiff(data: Data, numkeys: &[u8], args: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    zset_op_command(data, numkeys, args, ZSetOp::Diff, "zdiff", client)
+}
+
+/// `ZUNIONSTORE destination numkeys key [key ...] [WEIGHTS weight ...]
+/// [AGGREGATE SUM|MIN|MAX]`
+pub fn zunionstore(
+    data: Data,
+    destination: &[u8],
+    numkeys: &[u8],
+    args: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    zset_op_store(
+        data,
+        destination,
+        numkeys,
+        args,
+        ZSetOp::Union,
+        "zunionstore",
+        client,
+    )
+}
+
+/// `ZINTERSTORE destination numkeys key [key ...] [WEIGHTS weight ...]
+/// [AGGREGATE SUM|MIN|MAX]`
+pub fn zinterstore(
+    data: Data,
+    destination: &[u8],
+    numkeys: &[u8],
+    args: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    zset_op_store(
+        data,
+        destination,
+        numkeys,
+        args,
+        ZSetOp::Inter,
+        "zinterstore",
+        client,
+    )
+}
+
+/// `ZDIFFSTORE destination numkeys key [key ...]`
+pub fn zdiffstore(
+    data: Data,
+    destination: &[u8],
+    numkeys: &[u8],
+    args: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    zset_op_store(
+        data,
+        destination,
+        numkeys,
+        args,
+        ZSetOp::Diff,
+        "zdiffstore",
+        client,
+    )
+}
+
+fn pop(data: Data, key: &[u8], count: &[Vec<u8>], max: bool, client: &mut Client) -> Result<()> {
+    let count = match count {
+        [] => None,
+        [count] => match parse_int(count) {
+            Some(count) if count >= 0 => Some(count as usize),
+            _ => return send_error(client, "ERR value is out of range, must be positive"),
+        },
+        _ => return send_error(client, SYNTAX_ERROR),
+    };
+    let mut lock = data.lock().unwrap();
+    let zset = match lock.get_zset_mut(key) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => return client.send(&Value::Array(Vec::new())),
+        Err(_) => return send_error(client, WRONGTYPE),
+    };
+    let popped: Vec<(Vec<u8>, f64)> = std::iter::from_fn(|| zset.pop(max))
+        .take(count.unwrap_or(1))
+        .collect();
+    lock.remove_if_empty(key);
+    let elements = popped.iter().map(|(m, s)| (m.as_slice(), *s));
+    // without a count the reply is a single flat pair, whatever the protocol
+    let protocol = match count {
+        Some(_) => client.protocol,
+        None => Protocol::Resp2,
+    };
+    client.send(&elements_reply(elements, true, protocol))
+}
+
+/// `ZPOPMIN key [count]`
+pub fn zpopmin(data: Data, key: &[u8], count: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    pop(data, key, count, false, client)
+}
+
+/// `ZPOPMAX key [count]`
+pub fn zpopmax(data: Data, key: &[u8], count: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    pop(data, key, count, true, client)
+}
+
+fn pop_first(keys: Vec<Vec<u8>>, max: bool) -> Attempt {
+    Box::new(move |db: &mut Db| {
+        for key in &keys {
+            let zset = match db.get_zset_mut(key) {
+                Ok(Some(zset)) => zset,
+                Ok(None) => continue,
+                Err(_) => return Some(Value::Error(WRONGTYPE.to_string())),
+            };
+            let (member, score) = zset.pop(max).unwrap();
+            db.remove_if_empty(key);
+            return Some(Value::Array(vec![
+                Value::BulkString(key.clone()),
+                Value::BulkString(member),
+                score_reply(score),
+            ]));
+        }
+        None
+    })
+}
+
+fn blocking_pop(
+    data: Data,
+    key: &[u8],
+    rest: &[Vec<u8>],
+    max: bool,
+    client: &mut Client,
+) -> Result<()> {
+    let (timeout, rest) = rest.split_last().unwrap();
+    let timeout = match parse_timeout(timeout) {
+        Ok(timeout) => timeout,
+        Err(err) => return send_error(client, err),
+    };
+    let keys: Vec<Vec<u8>> = once(key.to_vec()).chain(rest.iter().cloned()).collect();
+    let reply = block_on(
+        &data,
+        keys.clone(),
+        timeout,
+        client.peer(),
+        pop_first(keys, max),
+    );
+    let Some(reply) = reply else {
+        return client.send(&Value::Null(NullKind::Array));
+    };
+    let sent = client.send(&reply);
+    // the member would be lost with a reply that didn't make it, so it goes
+    // back unless it was added again in the meantime
+    if sent.is_err()
+        && let Value::Array(popped) = &reply
+        && let [
+            Value::BulkString(key),
+            Value::BulkString(member),
+            Value::Double(MyFloat::Real(score)),
+        ] = popped.as_slice()
+    {
+        let mut lock = data.lock().unwrap();
+        if let Ok(zset) = lock.get_or_create_zset(key) {
+            if zset.score(member).is_none() {
+                zset.insert(member.clone(), *score);
+            }
+            lock.signal_ready(key);
+        }
+    }
+    sent
+}
+
+/// `BZPOPMIN key [key ...] timeout`
+pub fn bzpopmin(data: Data, key: &[u8], rest: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    blocking_pop(data, key, rest, false, client)
+}
+
+/// `BZPOPMAX key [key ...] timeout`
+pub fn bzpopmax(data: Data, key: &[u8], rest: &[Vec<u8>], client: &mut Client) -> Result<()> {
+    blocking_pop(data, key, rest, true, client)
+}
+
+/// `ZSCAN key cursor [MATCH pattern] [COUNT count]`. Scores come as bulk
+/// strings after their member, whatever the protocol.
+pub fn zscan(
+    data: Data,
+    key: &[u8],
+    cursor: &[u8],
+    args: &[Vec<u8>],
+    client: &mut Client,
+) -> Result<()> {
+    let (cursor, options) = match parse_scan_args(cursor, args, ScanTarget::ZSet) {
+        Ok(parsed) => parsed,
+        Err(err) => return send_error(client, &err),
+    };
+    with_zset(data, key, client, |zset, _| {
+        let mut elements = Vec::new();
+        let next = zset.scan(cursor, options.count, |member, score| {
+            if !options.matches(member) {
+                return;
+            }
+            elements.push(Value::BulkString(member.to_vec()));
+            elements.push(Value::BulkString(
+                MyFloat::Real(score).to_string().into_bytes(),
+            ));
+        });
+        Value::Array(vec![
+            Value::BulkString(next.to_string().into_bytes()),
+            Value::Array(elements),
+        ])
+    })
+}
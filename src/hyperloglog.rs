@@ -0,0 +1,464 @@
+//! HyperLogLog counters stored in string values, with the exact layout Redis
+//! uses so that values can be moved between the two.
+//!
+//! A counter is a 16 byte header followed by its 16384 registers of 6 bits,
+//! either packed one after the other (dense) or run-length encoded with the
+//! opcodes below (sparse), which is how every counter starts out:
+//!
+//! * `00xxxxxx`: `xxxxxx + 1` registers set to zero,
+//! * `01xxxxxx yyyyyyyy`: `xxxxxxyyyyyyyy + 1` registers set to zero,
+//! * `1vvvvvxx`: `xx + 1` registers set to `vvvvv + 1`.
+//!
+//! The header is the `HYLL` magic, the encoding, three unused bytes and the
+//! cached cardinality as a little endian integer whose most significant bit
+//! is set when the cache is stale.
+
+const MAGIC: &[u8] = b"HYLL";
+const HEADER_SIZE: usize = 16;
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+
+/// Bits of the hash used to pick a register.
+const P: u32 = 14;
+pub const REGISTERS: usize = 1 << P;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MAX: u8 = (1 << REGISTER_BITS) - 1;
+/// Bits of the hash left to count the run of zeros in.
+const Q: usize = 64 - P as usize;
+const DENSE_SIZE: usize = HEADER_SIZE + (REGISTERS * REGISTER_BITS).div_ceil(8);
+
+const SPARSE_XZERO_BIT: u8 = 0x40;
+const SPARSE_VAL_BIT: u8 = 0x80;
+const SPARSE_ZERO_MAX_LEN: usize = 64;
+const SPARSE_XZERO_MAX_LEN: usize = 16384;
+const SPARSE_VAL_MAX_VALUE: u8 = 32;
+const SPARSE_VAL_MAX_LEN: usize = 4;
+/// Size above which a sparse counter is turned into a dense one, Redis'
+/// default `hll-sparse-max-bytes`.
+const SPARSE_MAX_BYTES: usize = 3000;
+
+/// Constant of the cardinality estimator for an infinite number of
+/// registers, `1 / (2 ln 2)`.
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+pub const INVALID_HLL: &str = "WRONGTYPE Key is not a valid HyperLogLog string value.";
+pub const CORRUPTED_HLL: &str = "INVALIDOBJ Corrupted HLL object detected";
+
+/// Registers of a counter unpacked one per byte, used to merge counters.
+pub type Registers = [u8; REGISTERS];
+
+/// The 64 bit MurmurHash2 by Austin Appleby, reading the input as little
+/// endian like Redis does on every platform.
+fn murmurhash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+/// The register `element` goes to, and the length of the run of zeros
+/// ending in a one it brings, counting that one.
+fn pattern(element: &[u8]) -> (usize, u8) {
+    let hash = murmurhash64a(element, 0xadc8_3b19);
+    let index = hash as usize & (REGISTERS - 1);
+    // the extra bit stops the count at Q + 1
+    let hash = (hash >> P) | (1 << Q);
+    (index, hash.trailing_zeros() as u8 + 1)
+}
+
+/// A new counter, empty and sparse.
+pub fn new() -> Vec<u8> {
+    let mut hll = vec![0; HEADER_SIZE];
+    hll[..4].copy_from_slice(MAGIC);
+    hll[4] = SPARSE;
+    let len = SPARSE_XZERO_MAX_LEN - 1;
+    hll.extend([(len >> 8) as u8 | SPARSE_XZERO_BIT, len as u8]);
+    hll
+}
+
+/// Whether a string holds a counter, as far as the header tells.
+pub fn is_valid(hll: &[u8]) -> bool {
+    hll.len() >= HEADER_SIZE
+        && hll.starts_with(MAGIC)
+        && match hll[4] {
+            DENSE => hll.len() == DENSE_SIZE,
+            SPARSE => true,
+            _ => false,
+        }
+}
+
+pub fn is_dense(hll: &[u8]) -> bool {
+    hll[4] == DENSE
+}
+
+pub fn invalidate_cache(hll: &mut [u8]) {
+    hll[15] |= 0x80;
+}
+
+fn dense_get(registers: &[u8], index: usize) -> u8 {
+    let byte = index * REGISTER_BITS / 8;
+    let shift = index * REGISTER_BITS % 8;
+    let mut value = registers[byte] >> shift;
+    // registers that fit in their first byte, like the last one, don't
+    // need the next
+    if shift + REGISTER_BITS > 8 {
+        value |= registers[byte + 1] << (8 - shift);
+    }
+    value & REGISTER_MAX
+}
+
+fn dense_set(registers: &mut [u8], index: usize, value: u8) {
+    let byte = index * REGISTER_BITS / 8;
+    let shift = index * REGISTER_BITS % 8;
+    registers[byte] &= !(REGISTER_MAX << shift);
+    registers[byte] |= value << shift;
+    if shift + REGISTER_BITS > 8 {
+        registers[byte + 1] &= !(REGISTER_MAX >> (8 - shift));
+        registers[byte + 1] |= value >> (8 - shift);
+    }
+}
+
+/// A decoded sparse opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Opcode {
+    Zero(usize),
+    XZero(usize),
+    Val(u8, usize),
+}
+
+impl Opcode {
+    fn read(sparse: &[u8]) -> Opcode {
+        let byte = sparse[0];
+        if byte & SPARSE_VAL_BIT != 0 {
+            Opcode::Val(((byte >> 2) & 0x1f) + 1, (byte & 0x3) as usize + 1)
+        } else if byte & SPARSE_XZERO_BIT != 0 {
+            let low = sparse.get(1).copied().unwrap_or(0) as usize;
+            Opcode::XZero((((byte & 0x3f) as usize) << 8 | low) + 1)
+        } else {
+            Opcode::Zero((byte & 0x3f) as usize + 1)
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Opcode::XZero(_) => 2,
+            _ => 1,
+        }
+    }
+
+    fn run(self) -> usize {
+        match self {
+            Opcode::Zero(run) | Opcode::XZero(run) | Opcode::Val(_, run) => run,
+        }
+    }
+
+    /// Zeros take the shortest opcode that can hold the run.
+    fn zeros(run: usize) -> Opcode {
+        if run > SPARSE_ZERO_MAX_LEN {
+            Opcode::XZero(run)
+        } else {
+            Opcode::Zero(run)
+        }
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        match self {
+            Opcode::Zero(run) => out.push((run - 1) as u8),
+            Opcode::XZero(run) => {
+                out.extend([((run - 1) >> 8) as u8 | SPARSE_XZERO_BIT, (run - 1) as u8])
+            }
+            Opcode::Val(value, run) => {
+                out.push(((value - 1) << 2) | (run - 1) as u8 | SPARSE_VAL_BIT)
+            }
+        }
+    }
+}
+
+/// Iterates over the opcodes of a sparse counter, with their positions.
+fn opcodes(sparse: &[u8]) -> impl Iterator<Item = (usize, Opcode)> + '_ {
+    let mut at = 0;
+    std::iter::from_fn(move || {
+        if at >= sparse.len() {
+            return None;
+        }
+        let opcode = Opcode::read(&sparse[at..]);
+        let item = (at, opcode);
+        at += opcode.size();
+        Some(item)
+    })
+}
+
+/// Calls `visit` with every run of registers of a sparse counter, failing if
+/// they don't add up to the number of registers.
+fn sparse_runs(sparse: &[u8], mut visit: impl FnMut(usize, usize, u8)) -> Result<(), &'static str> {
+    let mut index = 0;
+    for (_, opcode) in opcodes(sparse) {
+        let run = opcode.run();
+        if index + run > REGISTERS {
+            return Err(CORRUPTED_HLL);
+        }
+        let value = match opcode {
+            Opcode::Val(value, _) => value,
+            _ => 0,
+        };
+        visit(index, run, value);
+        index += run;
+    }
+    if index != REGISTERS {
+        return Err(CORRUPTED_HLL);
+    }
+    Ok(())
+}
+
+/// Switches a counter to the dense encoding, keeping its header.
+fn to_dense(hll: &mut Vec<u8>) -> Result<(), &'static str> {
+    if hll[4] == DENSE {
+        return Ok(());
+    }
+    let mut dense = vec![0; DENSE_SIZE];
+    dense[..HEADER_SIZE].copy_from_slice(&hll[..HEADER_SIZE]);
+    dense[4] = DENSE;
+    sparse_runs(&hll[HEADER_SIZE..], |index, run, value| {
+        if value != 0 {
+            for i in index..index + run {
+                dense_set(&mut dense[HEADER_SIZE..], i, value);
+            }
+        }
+    })?;
+    *hll = dense;
+    Ok(())
+}
+
+/// Raises the register `index` of a sparse counter to `value`, splitting the
+/// run it is in the way Redis does so that both produce the same bytes.
+/// Returns whether the register changed.
+fn sparse_set(hll: &mut Vec<u8>, index: usize, value: u8) -> Result<bool, &'static str> {
+    if value > SPARSE_VAL_MAX_VALUE {
+        return promote(hll, index, value);
+    }
+    // find the opcode covering the register
+    let mut first = 0;
+    let mut prev = None;
+    let mut found = None;
+    for (at, opcode) in opcodes(&hll[HEADER_SIZE..]) {
+        if index < first + opcode.run() {
+            found = Some((at, opcode));
+            break;
+        }
+        prev = Some(at);
+        first += opcode.run();
+    }
+    let Some((at, opcode)) = found else {
+        return Err(CORRUPTED_HLL);
+    };
+    let at = HEADER_SIZE + at;
+    let last = first + opcode.run() - 1;
+
+    let replacement = match opcode {
+        Opcode::Val(old, _) if old >= value => return Ok(false),
+        Opcode::Val(_, 1) | Opcode::Zero(1) => vec![Opcode::Val(value, 1)],
+        _ => {
+            let around = |run| match opcode {
+                Opcode::Val(old, _) => Opcode::Val(old, run),
+                _ => Opcode::zeros(run),
+            };
+            let mut seq = Vec::with_capacity(3);
+            if index != first {
+                seq.push(around(index - first));
+            }
+            seq.push(Opcode::Val(value, 1));
+            if index != last {
+                seq.push(around(last - index));
+            }
+            seq
+        }
+    };
+    let mut bytes = Vec::with_capacity(5);
+    for opcode in replacement {
+        opcode.write(&mut bytes);
+    }
+    if bytes.len() > opcode.size() && hll.len() + bytes.len() - opcode.size() > SPARSE_MAX_BYTES {
+        return promote(hll, index, value);
+    }
+    hll.splice(at..at + opcode.size(), bytes);
+
+    // merge the values around the update that can be merged, looking at no
+    // more than five opcodes starting from the one before
+    let mut p = prev.map_or(HEADER_SIZE, |prev| HEADER_SIZE + prev);
+    for _ in 0..5 {
+        if p >= hll.len() {
+            break;
+        }
+        match Opcode::read(&hll[p..]) {
+            Opcode::Val(v1, len1) if p + 1 < hll.len() => {
+                if let Opcode::Val(v2, len2) = Opcode::read(&hll[p + 1..])
+                    && v1 == v2
+                    && len1 + len2 <= SPARSE_VAL_MAX_LEN
+                {
+                    let mut merged = Vec::with_capacity(1);
+                    Opcode::Val(v1, len1 + len2).write(&mut merged);
+                    hll.splice(p..p + 2, merged);
+                    continue;
+                }
+                p += 1;
+            }
+            opcode => p += opcode.size(),
+        }
+    }
+    invalidate_cache(hll);
+    Ok(true)
+}
+
+/// Turns a sparse counter that can't hold `value` into a dense one to set it.
+fn promote(hll: &mut Vec<u8>, index: usize, value: u8) -> Result<bool, &'static str> {
+    to_dense(hll)?;
+    set(hll, index, value)
+}
+
+/// Raises the register `index` to `value` if it is lower, returning whether
+/// it was.
+fn set(hll: &mut Vec<u8>, index: usize, value: u8) -> Result<bool, &'static str> {
+    if hll[4] == SPARSE {
+        return sparse_set(hll, index, value);
+    }
+    let registers = &mut hll[HEADER_SIZE..];
+    if dense_get(registers, index) >= value {
+        return Ok(false);
+    }
+    dense_set(registers, index, value);
+    invalidate_cache(hll);
+    Ok(true)
+}
+
+/// Adds an element, returning whether a register had to change for it.
+pub fn add(hll: &mut Vec<u8>, element: &[u8]) -> Result<bool, &'static str> {
+    let (index, count) = pattern(element);
+    set(hll, index, count)
+}
+
+/// Raises every register of `max` to the value of the same register in
+/// `hll` if it is higher.
+pub fn merge_into(max: &mut Registers, hll: &[u8]) -> Result<(), &'static str> {
+    let registers = &hll[HEADER_SIZE..];
+    if hll[4] == DENSE {
+        for (i, max) in max.iter_mut().enumerate() {
+            *max = (*max).max(dense_get(registers, i));
+        }
+        return Ok(());
+    }
+    sparse_runs(registers, |index, run, value| {
+        for max in &mut max[index..index + run] {
+            *max = (*max).max(value);
+        }
+    })
+}
+
+/// Stores `max` into `hll`, which is made dense first if `dense` is set.
+pub fn store(hll: &mut Vec<u8>, max: &Registers, dense: bool) -> Result<(), &'static str> {
+    if dense {
+        to_dense(hll)?;
+    }
+    for (i, value) in max.iter().enumerate() {
+        if *value != 0 {
+            set(hll, i, *value)?;
+        }
+    }
+    invalidate_cache(hll);
+    Ok(())
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+/// Estimates a cardinality from how many registers hold each value, with
+/// the estimator of Otmar Ertl's "New cardinality estimation algorithms for
+/// HyperLogLog sketches" that Redis uses.
+fn estimate(histogram: &[usize; Q + 2]) -> u64 {
+    let m = REGISTERS as f64;
+    let mut z = m * tau((m - histogram[Q + 1] as f64) / m);
+    for count in histogram[1..=Q].iter().rev() {
+        z += *count as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+/// Estimated cardinality of unpacked registers.
+pub fn count_registers(registers: &Registers) -> u64 {
+    let mut histogram = [0; Q + 2];
+    for value in registers {
+        histogram[*value as usize] += 1;
+    }
+    estimate(&histogram)
+}
+
+/// Estimated cardinality of a counter, cached in its header.
+pub fn count(hll: &mut [u8]) -> Result<u64, &'static str> {
+    let cache: [u8; 8] = hll[8..HEADER_SIZE].try_into().unwrap();
+    if cache[7] & 0x80 == 0 {
+        return Ok(u64::from_le_bytes(cache));
+    }
+    let mut histogram = [0; Q + 2];
+    let registers = &hll[HEADER_SIZE..];
+    if hll[4] == DENSE {
+        for i in 0..REGISTERS {
+            histogram[dense_get(registers, i) as usize] += 1;
+        }
+    } else {
+        sparse_runs(registers, |_, run, value| histogram[value as usize] += run)?;
+    }
+    let card = estimate(&histogram);
+    hll[8..HEADER_SIZE].copy_from_slice(&card.to_le_bytes());
+    Ok(card)
+}
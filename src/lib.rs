@@ -1,52 +1,137 @@
 use std::{
-    collections::{BTreeMap, BTreeSet, HashMap},
+    collections::BTreeSet,
     fmt::{Debug, Display},
     io::{Result, Write},
     sync::{Arc, Mutex},
 };
 
+pub mod bitmap;
+pub mod blocking;
+pub mod client;
+pub mod db;
+pub mod dict;
+pub mod geo;
+pub mod glob;
 pub mod handlers;
+pub mod hyperloglog;
+pub mod object;
 pub mod parse;
 pub mod router;
+pub mod scan;
+pub mod set;
+pub mod stream;
+pub mod util;
+pub mod zset;
 
-pub type Data = Arc<Mutex<HashMap<String, Value>>>;
+pub type Data = Arc<Mutex<db::Db>>;
+
+/// The numbered databases of the server, each behind its own lock. Clients
+/// pick one with SELECT and every command runs against that one.
+pub type Databases = Arc<Vec<Data>>;
+
+/// RESP version a connection speaks, chosen by the client through `HELLO`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+impl Protocol {
+    pub fn version(self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum ParseError {
+    /// The buffer ends in the middle of a frame.
+    Incomplete,
+    Io(String),
     SimpleStringParseError(String),
     IntegerParseError(String),
     BoolParseError(String),
     DoubleParseError(String),
+    BulkStringParseError(String),
     VerbatimStringParseError(String),
+    InlineParseError(String),
     UnknownDataType(String),
+    /// Well formed tokens that don't make a valid frame together.
+    InvalidFrame(String),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Incomplete => write!(f, "incomplete frame"),
+            ParseError::Io(s)
+            | ParseError::SimpleStringParseError(s)
+            | ParseError::IntegerParseError(s)
+            | ParseError::BoolParseError(s)
+            | ParseError::DoubleParseError(s)
+            | ParseError::BulkStringParseError(s)
+            | ParseError::VerbatimStringParseError(s)
+            | ParseError::InlineParseError(s)
+            | ParseError::UnknownDataType(s)
+            | ParseError::InvalidFrame(s) => write!(f, "{}", s),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct VerbatimString {
     pub enc: String,
-    pub data: String,
+    pub data: Vec<u8>,
+}
+
+impl VerbatimString {
+    /// Wire form of the payload: the three byte encoding, ':' and the raw data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(self.enc.len() + 1 + self.data.len());
+        ret.extend_from_slice(self.enc.as_bytes());
+        ret.push(b':');
+        ret.extend_from_slice(&self.data);
+        ret
+    }
 }
 
 impl Display for VerbatimString {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}:{}", self.enc, self.data)
+        write!(f, "{}:{}", self.enc, String::from_utf8_lossy(&self.data))
     }
 }
 
+/// The flavours of null a RESP stream can carry.
+///
+/// RESP3 has a single null (`_`), while RESP2 encodes it as a null bulk
+/// string (`$-1`) or a null array (`*-1`) depending on what the reply would
+/// have been. Keeping the kind around lets RESP2 clients get the exact form
+/// Redis would send them and lets parsed values be written back unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NullKind {
+    Null,
+    BulkString,
+    Array,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Value {
     String(String),
     Error(String),
     Integer(i64),
-    BulkString(String),
+    BulkString(Vec<u8>),
     Array(Vec<Value>),
-    Null(()),
+    Null(NullKind),
     Bool(bool),
     Double(MyFloat),
     BigNumber(String),
-    BulkError(String),
+    BulkError(Vec<u8>),
     VerbatimString(VerbatimString),
-    Map(BTreeMap<Value, Value>),
+    /// Key value pairs, sent in this order.
+    Map(Vec<(Value, Value)>),
     Set(BTreeSet<Value>),
     Push(Vec<Value>),
 }
@@ -57,12 +142,12 @@ impl Display for Value {
             Value::String(s) => s.to_string(),
             Value::Error(s) => s.to_string(),
             Value::Integer(i) => i.to_string(),
-            Value::BulkString(s) => s.to_string(),
-            Value::Null(()) => "None".to_string(),
+            Value::BulkString(s) => String::from_utf8_lossy(s).to_string(),
+            Value::Null(_) => "None".to_string(),
             Value::Bool(b) => b.to_string(),
             Value::Double(d) => d.to_string(),
             Value::BigNumber(s) => s.to_string(),
-            Value::BulkError(s) => s.to_string(),
+            Value::BulkError(s) => String::from_utf8_lossy(s).to_string(),
             Value::VerbatimString(s) => s.to_string(),
             _ => "ERROR".to_string(),
         };
@@ -71,8 +156,39 @@ impl Display for Value {
 }
 
 impl Value {
+    /// Raw payload of a bulk string, `None` for every other type.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::BulkString(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// RESP3 wire form of the value, with every null written in the form
+    /// it was created with so that parsed values round-trip exactly.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut ret = Vec::new();
+        self.encode_into(None, &mut ret);
+        ret
+    }
+
+    /// Wire form of the value for a client speaking `protocol`.
+    ///
+    /// RESP2 has no notion of the RESP3 only types, so they are downgraded
+    /// the way Redis does it: maps are flattened into arrays of key and value
+    /// pairs, sets and pushes become arrays, booleans become 1 or 0, doubles,
+    /// big numbers and verbatim strings become bulk strings and bulk errors
+    /// become simple errors. Nulls use the RESP2 form matching their kind, or
+    /// `_` for RESP3 clients.
+    pub fn encode(&self, protocol: Protocol) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.encode_into(Some(protocol), &mut ret);
+        ret
+    }
+
+    /// `protocol` being `None` means RESP3 with nulls kept as they are.
+    fn encode_into(&self, protocol: Option<Protocol>, ret: &mut Vec<u8>) {
+        let resp2 = protocol == Some(Protocol::Resp2);
         match self {
             Value::String(s) => {
                 ret.push(b'+');
@@ -89,24 +205,27 @@ impl Value {
                 ret.extend_from_slice(i.to_string().as_bytes());
                 ret.extend_from_slice(b"\r\n");
             }
-            Value::BulkString(s) => {
-                ret.push(b'$');
-                ret.extend_from_slice(s.len().to_string().as_bytes());
-                ret.extend_from_slice(b"\r\n");
-                ret.extend_from_slice(s.as_bytes());
-                ret.extend_from_slice(b"\r\n");
-            }
+            Value::BulkString(s) => encode_bulk(b'$', s, ret),
             Value::Array(a) => {
-                ret.push(b'*');
-                ret.extend_from_slice(a.len().to_string().as_bytes());
-                ret.extend_from_slice(b"\r\n");
+                encode_header(b'*', a.len(), ret);
                 for v in a {
-                    ret.extend_from_slice(v.to_bytes().as_slice());
+                    v.encode_into(protocol, ret);
                 }
             }
-            //TODO: null BulkString and null Array
-            Value::Null(_) => {
-                ret.extend_from_slice(b"_\r\n");
+            Value::Null(kind) => {
+                let kind = match protocol {
+                    Some(Protocol::Resp2) if *kind == NullKind::Null => NullKind::BulkString,
+                    Some(Protocol::Resp3) => NullKind::Null,
+                    _ => *kind,
+                };
+                ret.extend_from_slice(match kind {
+                    NullKind::Null => b"_\r\n",
+                    NullKind::BulkString => b"$-1\r\n",
+                    NullKind::Array => b"*-1\r\n",
+                });
+            }
+            Value::Bool(b) if resp2 => {
+                ret.extend_from_slice(if *b { b":1\r\n" } else { b":0\r\n" });
             }
             Value::Bool(b) => {
                 ret.push(b'#');
@@ -117,61 +236,68 @@ impl Value {
                 }
                 ret.extend_from_slice(b"\r\n");
             }
+            Value::Double(d) if resp2 => encode_bulk(b'$', d.to_string().as_bytes(), ret),
             Value::Double(d) => {
                 ret.push(b',');
                 ret.extend_from_slice(d.to_string().as_bytes());
                 ret.extend_from_slice(b"\r\n");
             }
+            Value::BigNumber(b) if resp2 => encode_bulk(b'$', b.as_bytes(), ret),
             Value::BigNumber(b) => {
                 ret.push(b'(');
                 ret.extend_from_slice(b.as_bytes());
                 ret.extend_from_slice(b"\r\n");
             }
-            Value::BulkError(e) => {
-                ret.push(b'!');
-                ret.extend_from_slice(e.len().to_string().as_bytes());
-                ret.extend_from_slice(b"\r\n");
-                ret.extend_from_slice(e.as_bytes());
-                ret.extend_from_slice(b"\r\n");
-            }
-            Value::VerbatimString(s) => {
-                ret.push(b'=');
-                let data = s.to_string();
-                ret.extend_from_slice(data.len().to_string().as_bytes());
-                ret.extend_from_slice(b"\r\n");
-                ret.extend_from_slice(data.as_bytes());
+            Value::BulkError(e) if resp2 => {
+                ret.push(b'-');
+                ret.extend(e.iter().map(|b| match b {
+                    b'\r' | b'\n' => b' ',
+                    b => *b,
+                }));
                 ret.extend_from_slice(b"\r\n");
             }
+            Value::BulkError(e) => encode_bulk(b'!', e, ret),
+            Value::VerbatimString(s) if resp2 => encode_bulk(b'$', &s.data, ret),
+            Value::VerbatimString(s) => encode_bulk(b'=', &s.to_bytes(), ret),
             Value::Map(m) => {
-                ret.push(b'%');
-                ret.extend_from_slice(m.len().to_string().as_bytes());
-                ret.extend_from_slice(b"\r\n");
+                if resp2 {
+                    encode_header(b'*', m.len() * 2, ret);
+                } else {
+                    encode_header(b'%', m.len(), ret);
+                }
                 for (k, v) in m.iter() {
-                    ret.extend_from_slice(&k.to_bytes());
-                    ret.extend_from_slice(&v.to_bytes());
+                    k.encode_into(protocol, ret);
+                    v.encode_into(protocol, ret);
                 }
             }
             Value::Set(s) => {
-                ret.push(b'~');
-                ret.extend_from_slice(s.len().to_string().as_bytes());
-                ret.extend_from_slice(b"\r\n");
+                encode_header(if resp2 { b'*' } else { b'~' }, s.len(), ret);
                 for v in s.iter() {
-                    ret.extend_from_slice(&v.to_bytes());
+                    v.encode_into(protocol, ret);
                 }
             }
             Value::Push(p) => {
-                ret.push(b'>');
-                ret.extend_from_slice(p.len().to_string().as_bytes());
-                ret.extend_from_slice(b"\r\n");
+                encode_header(if resp2 { b'*' } else { b'>' }, p.len(), ret);
                 for v in p {
-                    ret.extend_from_slice(v.to_bytes().as_slice());
+                    v.encode_into(protocol, ret);
                 }
             }
         }
-        ret
     }
 }
 
+fn encode_header(prefix: u8, len: usize, ret: &mut Vec<u8>) {
+    ret.push(prefix);
+    ret.extend_from_slice(len.to_string().as_bytes());
+    ret.extend_from_slice(b"\r\n");
+}
+
+fn encode_bulk(prefix: u8, data: &[u8], ret: &mut Vec<u8>) {
+    encode_header(prefix, data.len(), ret);
+    ret.extend_from_slice(data);
+    ret.extend_from_slice(b"\r\n");
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum MyFloat {
     Real(f64),
@@ -233,6 +359,10 @@ impl Ord for MyFloat {
     }
 }
 
+pub const SYNTAX_ERROR: &str = "ERR syntax error";
+pub const NOT_AN_INTEGER: &str = "ERR value is not an integer or out of range";
+pub const NOT_A_FLOAT: &str = "ERR value is not a valid float";
+
 pub fn send_error(stream: &mut dyn Write, msg: &str) -> Result<()> {
     let resp = Value::Error(msg.to_string()).to_bytes();
     stream.write_all(&resp)?;
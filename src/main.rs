@@ -1,34 +1,98 @@
-use rayon::spawn;
 use std::{
-    collections::HashMap,
     io::Result,
     net::TcpListener,
-    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
 };
 
-use redis_oxide::{Data, parse, router::route};
+use redis_oxide::{
+    Databases,
+    client::Client,
+    db::{DEFAULT_DATABASES, MAX_DATABASES, new_databases},
+    parse::Decoder,
+    router::route,
+    send_error,
+};
+
+/// How often keys nobody accesses anymore are checked for expiration.
+const EXPIRE_CYCLE_PERIOD: Duration = Duration::from_millis(100);
+
+/// How long one expiration check may keep going over all the databases, a
+/// quarter of the period like the default of Redis.
+const EXPIRE_CYCLE_BUDGET: Duration = Duration::from_millis(25);
+
+/// Number of databases, `--databases <count>` on the command line or
+/// `DEFAULT_DATABASES`. A count that isn't between 1 and `MAX_DATABASES`
+/// stops the server, as Redis does with a bad config.
+fn database_count() -> usize {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(at) = args.iter().position(|arg| arg == "--databases") else {
+        return DEFAULT_DATABASES;
+    };
+    match args.get(at + 1).map(|count| count.parse::<usize>()) {
+        Some(Ok(count)) if (1..=MAX_DATABASES).contains(&count) => count,
+        Some(_) => {
+            eprintln!(
+                "--databases: the number of databases must be between 1 and {}",
+                MAX_DATABASES
+            );
+            std::process::exit(1);
+        }
+        None => {
+            eprintln!("--databases: missing the number of databases");
+            std::process::exit(1);
+        }
+    }
+}
 
 fn main() -> Result<()> {
-    let data: Data = Arc::new(Mutex::new(HashMap::new()));
+    let dbs: Databases = new_databases(database_count());
+    let sweeper_dbs = dbs.clone();
+    thread::spawn(move || {
+        loop {
+            thread::sleep(EXPIRE_CYCLE_PERIOD);
+            let deadline = Instant::now() + EXPIRE_CYCLE_BUDGET;
+            for db in sweeper_dbs.iter() {
+                db.lock().unwrap().active_expire_cycle(deadline);
+            }
+        }
+    });
     //let mut lock = data.lock().unwrap();
     //lock.insert("hello".to_string(), redis_oxide::Value::String("world".to_string()));
     let listener = TcpListener::bind("127.0.0.1:6969")?;
-    for stream in listener.incoming() {
-        let data = data.clone();
-        spawn(move || {
+    for (client_id, stream) in (1..).zip(listener.incoming()) {
+        let dbs = dbs.clone();
+        // every connection gets its own thread, blocking commands park it
+        thread::spawn(move || {
             println!(".");
-            if let Ok(mut stream) = stream {
-                loop {
-                    let mut buf = [0];
-                    if let Ok(by) = stream.peek(&mut buf) {
-                        if by == 0 {
-                            break;
+            if let Ok(mut stream) = stream
+                && let Ok(writer) = stream.try_clone()
+            {
+                let mut client = Client::new(client_id, Box::new(writer));
+                if let Ok(socket) = stream.try_clone() {
+                    client = client.with_socket(socket);
+                }
+                let mut decoder = Decoder::new();
+                'conn: loop {
+                    loop {
+                        match decoder.next_frame() {
+                            Ok(Some(req)) => {
+                                println!("{:?}", req);
+                                let _ = route(req, &mut client, dbs.clone());
+                            }
+                            Ok(None) => break,
+                            Err(err) => {
+                                let _ = send_error(
+                                    &mut client,
+                                    &format!("ERR Protocol error: {}", err),
+                                );
+                                break 'conn;
+                            }
                         }
                     }
-                    let request = parse::parse(&mut stream);
-                    println!("{:?}", request);
-                    if let Ok(req) = request {
-                        let _ = route(req, &mut stream, data.clone());
+                    match decoder.read_from(&mut stream) {
+                        Ok(0) | Err(_) => break,
+                        Ok(_) => {}
                     }
                 }
             }
@@ -0,0 +1,151 @@
+use std::{borrow::Cow, collections::VecDeque, fmt::Display};
+
+use crate::{dict::Dict, set::Set, stream::Stream, zset::SortedSet};
+
+/// Error message for commands run against a key of another type.
+pub const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+/// The key exists but holds a value of another type than the command needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrongType;
+
+impl Display for WrongType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", WRONGTYPE)
+    }
+}
+
+/// Field-value pairs of a hash.
+pub type Hash = Dict<Vec<u8>, Vec<u8>>;
+
+/// A value stored in the keyspace.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    String(Vec<u8>),
+    /// A string holding an integer, kept as a number so that counters don't
+    /// go through text on every increment.
+    Int(i64),
+    List(VecDeque<Vec<u8>>),
+    Hash(Hash),
+    Set(Set),
+    ZSet(SortedSet),
+    Stream(Stream),
+}
+
+impl Object {
+    /// Name of the type as reported by the TYPE command.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::String(_) | Object::Int(_) => "string",
+            Object::List(_) => "list",
+            Object::Hash(_) => "hash",
+            Object::Set(_) => "set",
+            Object::ZSet(_) => "zset",
+            Object::Stream(_) => "stream",
+        }
+    }
+
+    /// The bytes of a string, integers being formatted on the fly.
+    pub fn as_string(&self) -> Result<Cow<'_, [u8]>, WrongType> {
+        match self {
+            Object::String(s) => Ok(Cow::Borrowed(s)),
+            Object::Int(n) => Ok(Cow::Owned(n.to_string().into_bytes())),
+            _ => Err(WrongType),
+        }
+    }
+
+    /// The bytes of a string for editing in place, which turns an integer
+    /// back into plain bytes.
+    pub fn as_string_mut(&mut self) -> Result<&mut Vec<u8>, WrongType> {
+        if let Object::Int(n) = self {
+            *self = Object::String(n.to_string().into_bytes());
+        }
+        match self {
+            Object::String(s) => Ok(s),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_list(&self) -> Result<&VecDeque<Vec<u8>>, WrongType> {
+        match self {
+            Object::List(l) => Ok(l),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Result<&mut VecDeque<Vec<u8>>, WrongType> {
+        match self {
+            Object::List(l) => Ok(l),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_hash(&self) -> Result<&Hash, WrongType> {
+        match self {
+            Object::Hash(h) => Ok(h),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_hash_mut(&mut self) -> Result<&mut Hash, WrongType> {
+        match self {
+            Object::Hash(h) => Ok(h),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_set(&self) -> Result<&Set, WrongType> {
+        match self {
+            Object::Set(s) => Ok(s),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_set_mut(&mut self) -> Result<&mut Set, WrongType> {
+        match self {
+            Object::Set(s) => Ok(s),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_zset(&self) -> Result<&SortedSet, WrongType> {
+        match self {
+            Object::ZSet(z) => Ok(z),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_zset_mut(&mut self) -> Result<&mut SortedSet, WrongType> {
+        match self {
+            Object::ZSet(z) => Ok(z),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_stream(&self) -> Result<&Stream, WrongType> {
+        match self {
+            Object::Stream(s) => Ok(s),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn as_stream_mut(&mut self) -> Result<&mut Stream, WrongType> {
+        match self {
+            Object::Stream(s) => Ok(s),
+            _ => Err(WrongType),
+        }
+    }
+
+    /// Whether this is a collection that ran out of elements, which Redis
+    /// never keeps around. Streams are the exception, they stay to remember
+    /// their last ID and consumer groups.
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            Object::String(_) | Object::Int(_) | Object::Stream(_) => false,
+            Object::List(l) => l.is_empty(),
+            Object::Hash(h) => h.is_empty(),
+            Object::Set(s) => s.is_empty(),
+            Object::ZSet(z) => z.is_empty(),
+        }
+    }
+}
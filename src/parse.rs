@@ -1,52 +1,201 @@
 use std::{
-    collections::{BTreeMap, BTreeSet},
-    io::Read,
+    collections::BTreeSet,
+    io::{Cursor, Read},
 };
 
-use crate::{MyFloat, ParseError, Value, VerbatimString};
+use crate::{MyFloat, NullKind, ParseError, Value, VerbatimString};
 
+/// Largest bulk payload a client may send, same default as Redis'
+/// `proto-max-bulk-len`.
+pub const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+
+/// Largest aggregate a client may announce before we start allocating for it.
+pub const MAX_AGGREGATE_LEN: i64 = 1024 * 1024;
+
+/// Most elements reserved up front for an aggregate, whatever its header
+/// announces. A bigger one grows as its elements actually arrive.
+const MAX_PREALLOC: usize = 1024;
+
+/// Deepest nesting of aggregates accepted. Requests are flat arrays, this
+/// only keeps a hostile frame like `*1\r\n` repeated forever from
+/// recursing until the stack overflows.
+pub const MAX_NESTING_DEPTH: usize = 32;
+
+/// Longest inline command line accepted, same as Redis' `PROTO_INLINE_MAX_SIZE`.
+pub const MAX_INLINE_LEN: usize = 64 * 1024;
+
+const READ_CHUNK: usize = 16 * 1024;
+
+/// Accumulates bytes read from a connection and cuts them into RESP frames.
+///
+/// Several pipelined commands can arrive in a single read and a single
+/// command can be split across many reads, so decoding never touches the
+/// socket directly: `read_from` appends whatever is available and
+/// `next_frame` hands out complete frames until the buffer runs dry.
+///
+/// The elements of an array that is still arriving are kept aside as they
+/// complete, so a big request sent slowly isn't parsed again from its start
+/// with every read.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    pos: usize,
+    partial: Option<PartialArray>,
+}
+
+/// The part of an array frame decoded so far.
+#[derive(Debug)]
+struct PartialArray {
+    len: usize,
+    elements: Vec<Value>,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Performs a single read from `stream` into the internal buffer and
+    /// returns the amount of bytes read, `Ok(0)` meaning end of stream.
+    pub fn read_from(&mut self, stream: &mut dyn Read) -> std::io::Result<usize> {
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        let len = self.buf.len();
+        self.buf.resize(len + READ_CHUNK, 0);
+        let res = stream.read(&mut self.buf[len..]);
+        self.buf.truncate(len + *res.as_ref().unwrap_or(&0));
+        res
+    }
+
+    /// Decodes the next buffered frame.
+    ///
+    /// Returns `Ok(None)` when the buffer holds only part of a frame (more
+    /// bytes have to be read first) and `Err` when the client sent something
+    /// that can never become a valid frame.
+    ///
+    /// Anything that does not start with a RESP type byte is treated as an
+    /// inline command and comes out as an array of bulk strings, the same
+    /// shape a RESP client would have sent. Blank inline lines are skipped.
+    pub fn next_frame(&mut self) -> Result<Option<Value>, ParseError> {
+        while self.pos < self.buf.len() {
+            let mut src = Cursor::new(&self.buf[self.pos..]);
+            let res = match self.partial.as_mut() {
+                Some(array) => parse_nested(&mut src, 1).map(|element| {
+                    array.elements.push(element);
+                    None
+                }),
+                None if self.buf[self.pos] == b'*' => {
+                    src.set_position(1);
+                    get_length(&mut src, MAX_AGGREGATE_LEN).map(|len| match len {
+                        Some(len) => {
+                            self.partial = Some(PartialArray {
+                                len,
+                                elements: Vec::with_capacity(len.min(MAX_PREALLOC)),
+                            });
+                            None
+                        }
+                        None => Some(Value::Null(NullKind::Array)),
+                    })
+                }
+                None if is_type_byte(self.buf[self.pos]) => parse_value(&mut src).map(Some),
+                None => parse_inline(&mut src).map(Some),
+            };
+            let frame = match res {
+                Ok(frame) => frame,
+                Err(ParseError::Incomplete) => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            self.pos += src.position() as usize;
+            let frame = match frame {
+                Some(frame) => frame,
+                None if self
+                    .partial
+                    .as_ref()
+                    .is_some_and(|array| array.elements.len() == array.len) =>
+                {
+                    Value::Array(self.partial.take().unwrap().elements)
+                }
+                None => continue,
+            };
+            match frame {
+                Value::Array(args) if args.is_empty() => {}
+                frame => return Ok(Some(frame)),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Blocks on `stream` until one whole frame has been read and decodes it.
 pub fn parse(stream: &mut dyn Read) -> Result<Value, ParseError> {
-    let mut buf = [1];
-    let _ = stream.read(&mut buf);
-    let datatype = buf[0] as char;
+    let mut decoder = Decoder::new();
+    loop {
+        if let Some(value) = decoder.next_frame()? {
+            return Ok(value);
+        }
+        match decoder.read_from(stream) {
+            Ok(0) => return Err(ParseError::Io("unexpected end of stream".to_string())),
+            Ok(_) => {}
+            Err(err) => return Err(ParseError::Io(err.to_string())),
+        }
+    }
+}
+
+/// Decodes one frame starting at the cursor position, leaving the cursor
+/// right after it. Fails with `ParseError::Incomplete` if the frame is cut.
+pub fn parse_value(src: &mut Cursor<&[u8]>) -> Result<Value, ParseError> {
+    parse_nested(src, 0)
+}
+
+/// `parse_value` for a frame found `depth` aggregates deep.
+fn parse_nested(src: &mut Cursor<&[u8]>, depth: usize) -> Result<Value, ParseError> {
+    if depth > MAX_NESTING_DEPTH {
+        return Err(ParseError::InvalidFrame(format!(
+            "aggregates nested deeper than {}",
+            MAX_NESTING_DEPTH
+        )));
+    }
+    let datatype = get_u8(src)? as char;
     match datatype {
-        '+' => Ok(Value::String(parse_simple_string(stream)?)),
-        '-' => Ok(Value::Error(parse_simple_string(stream)?)),
-        ':' => Ok(Value::Integer(parse_integer(stream)?)),
+        '+' => Ok(Value::String(parse_simple_string(src)?)),
+        '-' => Ok(Value::Error(parse_simple_string(src)?)),
+        ':' => Ok(Value::Integer(parse_integer(src)?)),
         '$' => {
-            let bstring = parse_bulk_string(stream)?;
+            let bstring = parse_bulk_string(src)?;
             Ok(match bstring {
                 Some(bstring) => Value::BulkString(bstring),
-                None => Value::Null(()),
+                None => Value::Null(NullKind::BulkString),
             })
         }
         '*' => {
-            let arr = parse_array(stream)?;
+            let arr = parse_array(src, depth)?;
             Ok(match arr {
                 Some(arr) => Value::Array(arr),
-                None => Value::Null(()),
+                None => Value::Null(NullKind::Array),
             })
         }
-        '_' => Ok(Value::Null(parse_null(stream)?)),
-        '#' => Ok(Value::Bool(parse_bool(stream)?)),
-        ',' => Ok(Value::Double(parse_double(stream)?)),
-        '(' => Ok(Value::BigNumber(parse_big_number(stream)?)),
+        '_' => {
+            parse_null(src)?;
+            Ok(Value::Null(NullKind::Null))
+        }
+        '#' => Ok(Value::Bool(parse_bool(src)?)),
+        ',' => Ok(Value::Double(parse_double(src)?)),
+        '(' => Ok(Value::BigNumber(parse_big_number(src)?)),
         '!' => {
-            let bstring = parse_bulk_string(stream)?;
-            Ok(match bstring {
-                Some(bstring) => Value::BulkError(bstring),
-                None => Value::Null(()),
-            })
+            let bstring = parse_bulk_string(src)?.ok_or(ParseError::BulkStringParseError(
+                "bulk error cannot be null".to_string(),
+            ))?;
+            Ok(Value::BulkError(bstring))
         }
-        '=' => Ok(Value::VerbatimString(parse_verbatim_string(stream)?)),
-        '%' => Ok(Value::Map(parse_map(stream)?)),
-        '~' => Ok(Value::Set(parse_set(stream)?)),
+        '=' => Ok(Value::VerbatimString(parse_verbatim_string(src)?)),
+        '%' => Ok(Value::Map(parse_map(src, depth)?)),
+        '~' => Ok(Value::Set(parse_set(src, depth)?)),
         '>' => {
-            let arr = parse_array(stream)?;
-            Ok(match arr {
-                Some(arr) => Value::Push(arr),
-                None => Value::Null(()),
-            })
+            let arr = parse_array(src, depth)?
+                .ok_or(ParseError::InvalidFrame("push cannot be null".to_string()))?;
+            Ok(Value::Push(arr))
         }
         datatype => Err(ParseError::UnknownDataType(format!(
             "ERROR: unknown datatype {:?}",
@@ -55,70 +204,241 @@ pub fn parse(stream: &mut dyn Read) -> Result<Value, ParseError> {
     }
 }
 
-pub fn parse_simple_string(stream: &mut dyn Read) -> Result<String, ParseError> {
-    let mut ret = Vec::new();
-    let mut buf = [1];
-    while stream.read(&mut buf).is_ok() {
-        if buf[0] as char == '\r' {
-            let _ = stream.read(&mut buf);
-            break;
+fn is_type_byte(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'+' | b'-'
+            | b':'
+            | b'$'
+            | b'*'
+            | b'_'
+            | b'#'
+            | b','
+            | b'('
+            | b'!'
+            | b'='
+            | b'%'
+            | b'~'
+            | b'>'
+    )
+}
+
+/// Parses a telnet style command line: words separated by whitespace and
+/// terminated by a newline, with the quoting rules of Redis' `sdssplitargs`.
+pub fn parse_inline(src: &mut Cursor<&[u8]>) -> Result<Value, ParseError> {
+    let start = src.position() as usize;
+    let buf: &[u8] = src.get_ref();
+    let Some(end) = buf[start..].iter().position(|b| *b == b'\n') else {
+        if buf.len() - start > MAX_INLINE_LEN {
+            return Err(ParseError::InlineParseError(
+                "too big inline request".to_string(),
+            ));
+        }
+        return Err(ParseError::Incomplete);
+    };
+    src.set_position((start + end + 1) as u64);
+    let mut line = &buf[start..start + end];
+    if let Some(stripped) = line.strip_suffix(b"\r") {
+        line = stripped;
+    }
+    let args = split_args(line).ok_or(ParseError::InlineParseError(
+        "unbalanced quotes in request".to_string(),
+    ))?;
+    Ok(Value::Array(
+        args.into_iter().map(Value::BulkString).collect(),
+    ))
+}
+
+/// Splits a line into arguments, `None` meaning the quotes don't balance.
+///
+/// Double quoted words understand `\n`, `\r`, `\t`, `\b`, `\a` and `\xHH`
+/// escapes, single quoted words only `\'`. A closing quote has to be followed
+/// by whitespace or the end of the line.
+pub fn split_args(line: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut args = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Some(args);
+        }
+        let mut current = Vec::new();
+        let mut in_double = false;
+        let mut in_single = false;
+        loop {
+            if in_double {
+                let c = *line.get(i)?;
+                if c == b'\\'
+                    && i + 3 < line.len()
+                    && line[i + 1] == b'x'
+                    && line[i + 2].is_ascii_hexdigit()
+                    && line[i + 3].is_ascii_hexdigit()
+                {
+                    let hex = std::str::from_utf8(&line[i + 2..i + 4]).ok()?;
+                    current.push(u8::from_str_radix(hex, 16).ok()?);
+                    i += 3;
+                } else if c == b'\\' && i + 1 < line.len() {
+                    i += 1;
+                    current.push(match line[i] {
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'b' => 0x08,
+                        b'a' => 0x07,
+                        other => other,
+                    });
+                } else if c == b'"' {
+                    if line.get(i + 1).is_some_and(|c| !c.is_ascii_whitespace()) {
+                        return None;
+                    }
+                    i += 1;
+                    break;
+                } else {
+                    current.push(c);
+                }
+            } else if in_single {
+                let c = *line.get(i)?;
+                if c == b'\\' && line.get(i + 1) == Some(&b'\'') {
+                    i += 1;
+                    current.push(b'\'');
+                } else if c == b'\'' {
+                    if line.get(i + 1).is_some_and(|c| !c.is_ascii_whitespace()) {
+                        return None;
+                    }
+                    i += 1;
+                    break;
+                } else {
+                    current.push(c);
+                }
+            } else {
+                match line.get(i) {
+                    None => break,
+                    Some(c) if c.is_ascii_whitespace() => break,
+                    Some(b'"') => in_double = true,
+                    Some(b'\'') => in_single = true,
+                    Some(c) => current.push(*c),
+                }
+            }
+            i += 1;
         }
-        ret.push(buf[0]);
+        args.push(current);
+    }
+}
+
+fn get_u8(src: &mut Cursor<&[u8]>) -> Result<u8, ParseError> {
+    let pos = src.position() as usize;
+    let byte = *src.get_ref().get(pos).ok_or(ParseError::Incomplete)?;
+    src.set_position(pos as u64 + 1);
+    Ok(byte)
+}
+
+/// Returns the bytes up to the next CRLF and moves the cursor past it.
+/// Lines are capped at `MAX_INLINE_LEN` like inline commands, so that a peer
+/// never sending the CRLF can't grow the buffer forever.
+fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], ParseError> {
+    let start = src.position() as usize;
+    let buf: &'a [u8] = src.get_ref();
+    let window = &buf[start..buf.len().min(start + MAX_INLINE_LEN + 2)];
+    let Some(end) = window.windows(2).position(|w| w == b"\r\n") else {
+        if window.len() == MAX_INLINE_LEN + 2 {
+            return Err(ParseError::InvalidFrame("too big line".to_string()));
+        }
+        return Err(ParseError::Incomplete);
+    };
+    src.set_position((start + end + 2) as u64);
+    Ok(&buf[start..start + end])
+}
+
+/// Returns exactly `len` bytes followed by a CRLF and moves the cursor past it.
+fn get_exact<'a>(src: &mut Cursor<&'a [u8]>, len: usize) -> Result<&'a [u8], ParseError> {
+    let start = src.position() as usize;
+    let buf: &'a [u8] = src.get_ref();
+    if buf.len() - start < len + 2 {
+        return Err(ParseError::Incomplete);
     }
-    String::from_utf8(ret).map_err(|err| ParseError::SimpleStringParseError(err.to_string()))
+    if &buf[start + len..start + len + 2] != b"\r\n" {
+        return Err(ParseError::BulkStringParseError(
+            "bulk string is not terminated by CRLF".to_string(),
+        ));
+    }
+    src.set_position((start + len + 2) as u64);
+    Ok(&buf[start..start + len])
+}
+
+/// Reads an aggregate length, `None` standing for the RESP2 null (`-1`).
+fn get_length(src: &mut Cursor<&[u8]>, max: i64) -> Result<Option<usize>, ParseError> {
+    let n = parse_integer(src)?;
+    if n == -1 {
+        return Ok(None);
+    }
+    if !(0..=max).contains(&n) {
+        return Err(ParseError::IntegerParseError(format!(
+            "invalid length {}",
+            n
+        )));
+    }
+    Ok(Some(n as usize))
+}
+
+pub fn parse_simple_string(src: &mut Cursor<&[u8]>) -> Result<String, ParseError> {
+    let line = get_line(src)?;
+    String::from_utf8(line.to_vec())
+        .map_err(|err| ParseError::SimpleStringParseError(err.to_string()))
 }
 
-pub fn parse_integer(stream: &mut dyn Read) -> Result<i64, ParseError> {
-    let int_as_str = parse_simple_string(stream)?;
+pub fn parse_integer(src: &mut Cursor<&[u8]>) -> Result<i64, ParseError> {
+    let int_as_str = parse_simple_string(src)?;
     int_as_str
         .parse()
         .map_err(|err: std::num::ParseIntError| ParseError::IntegerParseError(err.to_string()))
 }
 
-pub fn parse_bulk_string(stream: &mut dyn Read) -> Result<Option<String>, ParseError> {
-    let string_len = parse_integer(stream)?;
-    if string_len == -1 {
+pub fn parse_bulk_string(src: &mut Cursor<&[u8]>) -> Result<Option<Vec<u8>>, ParseError> {
+    let Some(string_len) = get_length(src, MAX_BULK_LEN)? else {
         return Ok(None);
-    }
-    let string = parse_simple_string(stream)?;
-    Ok(Some(string))
+    };
+    Ok(Some(get_exact(src, string_len)?.to_vec()))
 }
 
-pub fn parse_array(stream: &mut dyn Read) -> Result<Option<Vec<Value>>, ParseError> {
-    let n = parse_integer(stream)?;
-    if n == -1 {
+/// Reads the elements of an aggregate found `depth` aggregates deep.
+pub fn parse_array(
+    src: &mut Cursor<&[u8]>,
+    depth: usize,
+) -> Result<Option<Vec<Value>>, ParseError> {
+    let Some(n) = get_length(src, MAX_AGGREGATE_LEN)? else {
         return Ok(None);
-    }
-    let mut ret = Vec::new();
+    };
+    let mut ret = Vec::with_capacity(n.min(MAX_PREALLOC));
     for _ in 0..n {
-        ret.push(parse(stream)?);
+        ret.push(parse_nested(src, depth + 1)?);
     }
     Ok(Some(ret))
 }
 
-pub fn parse_null(stream: &mut dyn Read) -> Result<(), ParseError> {
-    let mut buf = [1];
-    let _ = stream.read(&mut buf);
-    let _ = stream.read(&mut buf);
-    Ok(())
+pub fn parse_null(src: &mut Cursor<&[u8]>) -> Result<(), ParseError> {
+    match get_line(src)? {
+        b"" => Ok(()),
+        _ => Err(ParseError::UnknownDataType(
+            "ERROR: null cannot carry data".to_string(),
+        )),
+    }
 }
 
-pub fn parse_bool(stream: &mut dyn Read) -> Result<bool, ParseError> {
-    let mut buf = [1];
-    let b = stream.read(&mut buf);
-    if b.is_ok() {
-        let mut temp_buf = [1];
-        let _ = stream.read(&mut temp_buf);
-        let _ = stream.read(&mut temp_buf);
-        if buf[0] == b't' { Ok(true) } else { Ok(false) }
-    } else {
-        b.map_err(|err| ParseError::BoolParseError(err.to_string()))
-            .map(|_| false)
+pub fn parse_bool(src: &mut Cursor<&[u8]>) -> Result<bool, ParseError> {
+    match get_line(src)? {
+        b"t" => Ok(true),
+        b"f" => Ok(false),
+        other => Err(ParseError::BoolParseError(format!(
+            "expected 't' or 'f', got {:?}",
+            String::from_utf8_lossy(other)
+        ))),
     }
 }
 
-pub fn parse_double(stream: &mut dyn Read) -> Result<MyFloat, ParseError> {
-    let f_as_str = parse_simple_string(stream)?;
+pub fn parse_double(src: &mut Cursor<&[u8]>) -> Result<MyFloat, ParseError> {
+    let f_as_str = parse_simple_string(src)?;
     f_as_str
         .parse::<f64>()
         .map_err(|err: std::num::ParseFloatError| ParseError::DoubleParseError(err.to_string()))
@@ -126,44 +446,41 @@ pub fn parse_double(stream: &mut dyn Read) -> Result<MyFloat, ParseError> {
 }
 
 //TODO: create a custom struct to represent a big number
-pub fn parse_big_number(stream: &mut dyn Read) -> Result<String, ParseError> {
-    parse_simple_string(stream)
+pub fn parse_big_number(src: &mut Cursor<&[u8]>) -> Result<String, ParseError> {
+    parse_simple_string(src)
 }
 
-pub fn parse_verbatim_string(stream: &mut dyn Read) -> Result<VerbatimString, ParseError> {
-    let binding = parse_simple_string(stream)?;
-    let mut raw_str = binding.split(':');
-    let enc = raw_str
-        .next()
-        .ok_or(ParseError::VerbatimStringParseError(
+pub fn parse_verbatim_string(src: &mut Cursor<&[u8]>) -> Result<VerbatimString, ParseError> {
+    let raw = parse_bulk_string(src)?.ok_or(ParseError::VerbatimStringParseError(
+        "Encoding Error, verbatim string cannot be null".to_string(),
+    ))?;
+    if raw.len() < 4 || raw[3] != b':' {
+        return Err(ParseError::VerbatimStringParseError(
             "Encoding Error, no ':' delimiter between encoding type and data".to_string(),
-        ))?
-        .to_string();
-    let data = raw_str
-        .next()
-        .ok_or(ParseError::VerbatimStringParseError(
-            "Encoding Error, no ':' delimiter between encoding type and data".to_string(),
-        ))?
-        .to_string();
+        ));
+    }
+    let enc = String::from_utf8(raw[..3].to_vec())
+        .map_err(|err| ParseError::VerbatimStringParseError(err.to_string()))?;
+    let data = raw[4..].to_vec();
     Ok(VerbatimString { enc, data })
 }
 
-pub fn parse_map(stream: &mut dyn Read) -> Result<BTreeMap<Value, Value>, ParseError> {
-    let n = parse_integer(stream)?;
-    let mut ret = BTreeMap::new();
+pub fn parse_map(src: &mut Cursor<&[u8]>, depth: usize) -> Result<Vec<(Value, Value)>, ParseError> {
+    let n = get_length(src, MAX_AGGREGATE_LEN)?.unwrap_or(0);
+    let mut ret = Vec::with_capacity(n.min(MAX_PREALLOC));
     for _ in 0..n {
-        let key = parse(stream)?;
-        let value = parse(stream)?;
-        ret.insert(key, value);
+        let key = parse_nested(src, depth + 1)?;
+        let value = parse_nested(src, depth + 1)?;
+        ret.push((key, value));
     }
     Ok(ret)
 }
 
-pub fn parse_set(stream: &mut dyn Read) -> Result<BTreeSet<Value>, ParseError> {
-    let n = parse_integer(stream)?;
+pub fn parse_set(src: &mut Cursor<&[u8]>, depth: usize) -> Result<BTreeSet<Value>, ParseError> {
+    let n = get_length(src, MAX_AGGREGATE_LEN)?.unwrap_or(0);
     let mut ret = BTreeSet::new();
     for _ in 0..n {
-        let value = parse(stream)?;
+        let value = parse_nested(src, depth + 1)?;
         ret.insert(value);
     }
     Ok(ret)
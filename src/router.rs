@@ -1,57 +1,120 @@
-use std::io::{Result, Write};
+use std::io::Result;
 
 use crate::{
-    Data, Value,
+    Databases, Value,
+    client::Client,
     handlers::{
-        command_handlers::{decr, del, get, incr, set},
+        bitmap_handlers::{bitcount, bitfield, bitfield_ro, bitop, bitpos, getbit, setbit},
+        command_handlers::{
+            append, decr, decrby, del, get, getdel, getex, getrange, getset, incr, incrby,
+            incrbyfloat, lcs, mget, mset, msetnx, set, setrange, strlen,
+        },
+        connection_handlers::{hello, select},
+        geo_handlers::{geoadd, geodist, geohash, geopos, geosearch, geosearchstore},
+        hash_handlers::{
+            hdel, hexists, hget, hgetall, hincrby, hkeys, hlen, hmget, hscan, hset, hvals,
+        },
+        hyperloglog_handlers::{pfadd, pfcount, pfmerge},
+        key_handlers::{
+            copy, dbsize, exists, expire, expireat, flushall, flushdb, keys, move_, persist,
+            pexpire, pexpireat, pttl, randomkey, rename, renamenx, scan, swapdb, touch, ttl, type_,
+            unlink,
+        },
+        list_handlers::{
+            blmove, blpop, brpop, lindex, linsert, llen, lmove, lpop, lpush, lrange, lrem, lset,
+            ltrim, rpop, rpush,
+        },
+        set_handlers::{
+            sadd, scard, sdiff, sdiffstore, sinter, sintercard, sinterstore, sismember, smembers,
+            smismember, smove, spop, srandmember, srem, sscan, sunion, sunionstore,
+        },
         start_handlers::handle_command_docs,
+        stream_handlers::{
+            xack, xadd, xautoclaim, xclaim, xdel, xgroup, xinfo, xlen, xpending, xrange, xread,
+            xreadgroup, xrevrange, xtrim,
+        },
+        zset_handlers::{
+            bzpopmax, bzpopmin, zadd, zcard, zdiff, zdiffstore, zincrby, zinter, zinterstore,
+            zpopmax, zpopmin, zrange, zrank, zrem, zremrangebylex, zremrangebyrank,
+            zremrangebyscore, zrevrank, zscan, zscore, zunion, zunionstore,
+        },
     },
     send_error,
 };
 
 macro_rules! handle {
-    //command: "command name", arguments for command
-    {$($data:ident)?, $stream:ident, $arr:ident, $command:ident, $key:tt $(,$arg:tt)*} => {
-        let $key = $arr.next();
-        let Some(crate::Value::BulkString($key)) = $key else {
+    //command: "command name", any amount of arguments passed as a slice
+    {$($data:ident)?, $stream:ident, $arr:ident, $command:ident, ..} => {
+        let args: Vec<Vec<u8>> = $arr.filter_map(|v| v.as_bytes().map(<[u8]>::to_vec)).collect();
+        return $command($($data.clone(),)? &args, $stream);
+    };
+    //command: "command name", arguments for command, then at least one more argument as a slice
+    {$($data:ident)?, $stream:ident, $arr:ident, $command:ident, $key:tt $(,$arg:tt)*; ..+} => {
+        handle! {@bind $stream, $arr, $command, $key $(,$arg)*}
+        let rest: Vec<Vec<u8>> = $arr.filter_map(|v| v.as_bytes().map(<[u8]>::to_vec)).collect();
+        if rest.is_empty() {
             return crate::send_error(
                 $stream,
                 &format!(
-                    "Error, wrong amount of arguments for '{}' command",
+                    "ERR wrong number of arguments for '{}' command",
                     stringify!($command)
                 ),
             );
-        };
-        $(
-        let $arg = $arr.next();
-        let Some($arg) = $arg else {
+        }
+        return $command($($data.clone(),)? $key, $($arg,)* &rest, $stream);
+    };
+    //command: "command name", arguments for command, then the remaining ones as a slice
+    {$($data:ident)?, $stream:ident, $arr:ident, $command:ident, $key:tt $(,$arg:tt)*; ..} => {
+        handle! {@bind $stream, $arr, $command, $key $(,$arg)*}
+        let rest: Vec<Vec<u8>> = $arr.filter_map(|v| v.as_bytes().map(<[u8]>::to_vec)).collect();
+        return $command($($data.clone(),)? $key, $($arg,)* &rest, $stream);
+    };
+    //command: "command name", arguments for command
+    {$($data:ident)?, $stream:ident, $arr:ident, $command:ident, $key:tt $(,$arg:tt)*} => {
+        handle! {@bind $stream, $arr, $command, $key $(,$arg)*}
+        if $arr.next().is_some() {
             return crate::send_error(
                 $stream,
                 &format!(
-                    "Error, wrong amount of arguments for '{}' command",
+                    "ERR wrong number of arguments for '{}' command",
                     stringify!($command)
                 ),
             );
-        };
-        )*
-        return $command($($data.clone(),)? $key, $($arg.clone(),)* $stream);
+        }
+        return $command($($data.clone(),)? $key, $($arg,)* $stream);
     };
     {$data:ident, $stream:ident, $arr:ident, $command:ident} => {
-                    let mut keys = $arr.map(|v| v.to_string());
-                    if keys.len() == 0 {
+                    let keys: Vec<Vec<u8>> = $arr.filter_map(|v| v.as_bytes().map(<[u8]>::to_vec)).collect();
+                    if keys.is_empty() {
                         return crate::send_error(
                             $stream,
                             &format!(
-                                "Error, wrong amount of arguments for '{}' command",
+                                "ERR wrong number of arguments for '{}' command",
                                 stringify!($command)
                             ),
                         );
                     }
-                    $command($data.clone(), &mut keys, $stream)
+                    $command($data.clone(), &mut keys.into_iter(), $stream)
+    };
+    //binds the next bulk string arguments to the given names
+    {@bind $stream:ident, $arr:ident, $command:ident, $($arg:tt),*} => {
+        $(
+        let $arg = $arr.next();
+        let Some(crate::Value::BulkString($arg)) = $arg else {
+            return crate::send_error(
+                $stream,
+                &format!(
+                    "ERR wrong number of arguments for '{}' command",
+                    stringify!($command)
+                ),
+            );
+        };
+        )*
     };
 }
 
-pub fn route(req: Value, stream: &mut dyn Write, data: Data) -> Result<()> {
+pub fn route(req: Value, stream: &mut Client, dbs: Databases) -> Result<()> {
+    let data = dbs[stream.db].clone();
     match req {
         Value::Array(arr) => {
             let mut arr = arr.iter();
@@ -59,25 +122,427 @@ pub fn route(req: Value, stream: &mut dyn Write, data: Data) -> Result<()> {
                 Some(command) => command,
                 None => return Ok(()),
             };
-            match command {
-                Value::BulkString(cmd) if cmd.to_lowercase() == "command" => {
+            let Value::BulkString(command) = command else {
+                return send_error(stream, "ERR unknown command");
+            };
+            match command.to_ascii_lowercase().as_slice() {
+                b"command" => {
                     handle! {, stream, arr, handle_command_docs, arg}
                 }
-                Value::BulkString(cmd) if cmd.to_lowercase() == "get" => {
+                b"hello" => {
+                    handle! {, stream, arr, hello, ..}
+                }
+                b"select" => {
+                    handle! {dbs, stream, arr, select, index}
+                }
+                b"get" => {
                     handle! {data, stream, arr, get, key}
                 }
-                Value::BulkString(cmd) if cmd.to_lowercase() == "set" => {
-                    handle! {data, stream, arr, set, key, val}
+                b"set" => {
+                    handle! {data, stream, arr, set, key, val; ..}
                 }
-                Value::BulkString(cmd) if cmd.to_lowercase() == "incr" => {
+                b"incr" => {
                     handle! {data, stream, arr, incr, key}
                 }
-                Value::BulkString(cmd) if cmd.to_lowercase() == "decr" => {
+                b"decr" => {
                     handle! {data, stream, arr, decr, key}
                 }
-                Value::BulkString(cmd) if cmd.to_lowercase() == "del" => {
+                b"incrby" => {
+                    handle! {data, stream, arr, incrby, key, increment}
+                }
+                b"decrby" => {
+                    handle! {data, stream, arr, decrby, key, decrement}
+                }
+                b"incrbyfloat" => {
+                    handle! {data, stream, arr, incrbyfloat, key, increment}
+                }
+                b"del" => {
                     handle! {data, stream, arr, del}
                 }
+                b"append" => {
+                    handle! {data, stream, arr, append, key, value}
+                }
+                b"strlen" => {
+                    handle! {data, stream, arr, strlen, key}
+                }
+                b"getrange" => {
+                    handle! {data, stream, arr, getrange, key, start, end}
+                }
+                b"setrange" => {
+                    handle! {data, stream, arr, setrange, key, offset, value}
+                }
+                b"getdel" => {
+                    handle! {data, stream, arr, getdel, key}
+                }
+                b"getex" => {
+                    handle! {data, stream, arr, getex, key; ..}
+                }
+                b"getset" => {
+                    handle! {data, stream, arr, getset, key, value}
+                }
+                b"mget" => {
+                    handle! {data, stream, arr, mget, ..}
+                }
+                b"mset" => {
+                    handle! {data, stream, arr, mset, ..}
+                }
+                b"msetnx" => {
+                    handle! {data, stream, arr, msetnx, ..}
+                }
+                b"lcs" => {
+                    handle! {data, stream, arr, lcs, key1, key2; ..}
+                }
+                b"setbit" => {
+                    handle! {data, stream, arr, setbit, key, offset, value}
+                }
+                b"getbit" => {
+                    handle! {data, stream, arr, getbit, key, offset}
+                }
+                b"bitcount" => {
+                    handle! {data, stream, arr, bitcount, key; ..}
+                }
+                b"bitpos" => {
+                    handle! {data, stream, arr, bitpos, key, bit; ..}
+                }
+                b"bitop" => {
+                    handle! {data, stream, arr, bitop, operation, destkey; ..+}
+                }
+                b"bitfield" => {
+                    handle! {data, stream, arr, bitfield, key; ..}
+                }
+                b"bitfield_ro" => {
+                    handle! {data, stream, arr, bitfield_ro, key; ..}
+                }
+                b"pfadd" => {
+                    handle! {data, stream, arr, pfadd, key; ..}
+                }
+                b"pfcount" => {
+                    handle! {data, stream, arr, pfcount, key; ..}
+                }
+                b"pfmerge" => {
+                    handle! {data, stream, arr, pfmerge, destkey; ..}
+                }
+                b"exists" => {
+                    handle! {data, stream, arr, exists}
+                }
+                b"touch" => {
+                    handle! {data, stream, arr, touch}
+                }
+                b"unlink" => {
+                    handle! {data, stream, arr, unlink}
+                }
+                b"type" => {
+                    handle! {data, stream, arr, type_, ..}
+                }
+                b"rename" => {
+                    handle! {data, stream, arr, rename, key, newkey}
+                }
+                b"renamenx" => {
+                    handle! {data, stream, arr, renamenx, key, newkey}
+                }
+                b"copy" => {
+                    handle! {dbs, stream, arr, copy, source, destination; ..}
+                }
+                b"keys" => {
+                    handle! {data, stream, arr, keys, pattern}
+                }
+                b"scan" => {
+                    handle! {data, stream, arr, scan, cursor; ..}
+                }
+                b"randomkey" => {
+                    handle! {data, stream, arr, randomkey, ..}
+                }
+                b"dbsize" => {
+                    handle! {data, stream, arr, dbsize, ..}
+                }
+                b"flushdb" => {
+                    handle! {data, stream, arr, flushdb, ..}
+                }
+                b"flushall" => {
+                    handle! {dbs, stream, arr, flushall, ..}
+                }
+                b"swapdb" => {
+                    handle! {dbs, stream, arr, swapdb, index1, index2}
+                }
+                b"move" => {
+                    handle! {dbs, stream, arr, move_, ..}
+                }
+                b"expire" => {
+                    handle! {data, stream, arr, expire, key, seconds; ..}
+                }
+                b"pexpire" => {
+                    handle! {data, stream, arr, pexpire, key, milliseconds; ..}
+                }
+                b"expireat" => {
+                    handle! {data, stream, arr, expireat, key, timestamp; ..}
+                }
+                b"pexpireat" => {
+                    handle! {data, stream, arr, pexpireat, key, timestamp; ..}
+                }
+                b"ttl" => {
+                    handle! {data, stream, arr, ttl, key}
+                }
+                b"pttl" => {
+                    handle! {data, stream, arr, pttl, key}
+                }
+                b"persist" => {
+                    handle! {data, stream, arr, persist, key}
+                }
+                b"lpush" => {
+                    handle! {data, stream, arr, lpush, key; ..+}
+                }
+                b"rpush" => {
+                    handle! {data, stream, arr, rpush, key; ..+}
+                }
+                b"lpop" => {
+                    handle! {data, stream, arr, lpop, key; ..}
+                }
+                b"rpop" => {
+                    handle! {data, stream, arr, rpop, key; ..}
+                }
+                b"lrange" => {
+                    handle! {data, stream, arr, lrange, key, start, stop}
+                }
+                b"llen" => {
+                    handle! {data, stream, arr, llen, key}
+                }
+                b"lindex" => {
+                    handle! {data, stream, arr, lindex, key, index}
+                }
+                b"lset" => {
+                    handle! {data, stream, arr, lset, key, index, element}
+                }
+                b"lrem" => {
+                    handle! {data, stream, arr, lrem, key, count, element}
+                }
+                b"ltrim" => {
+                    handle! {data, stream, arr, ltrim, key, start, stop}
+                }
+                b"linsert" => {
+                    handle! {data, stream, arr, linsert, key, position, pivot, element}
+                }
+                b"lmove" => {
+                    handle! {data, stream, arr, lmove, source, destination, wherefrom, whereto}
+                }
+                b"blpop" => {
+                    handle! {data, stream, arr, blpop, key; ..+}
+                }
+                b"brpop" => {
+                    handle! {data, stream, arr, brpop, key; ..+}
+                }
+                b"hset" => {
+                    handle! {data, stream, arr, hset, key; ..+}
+                }
+                b"hget" => {
+                    handle! {data, stream, arr, hget, key, field}
+                }
+                b"hmget" => {
+                    handle! {data, stream, arr, hmget, key; ..+}
+                }
+                b"hdel" => {
+                    handle! {data, stream, arr, hdel, key; ..+}
+                }
+                b"hgetall" => {
+                    handle! {data, stream, arr, hgetall, key}
+                }
+                b"hincrby" => {
+                    handle! {data, stream, arr, hincrby, key, field, increment}
+                }
+                b"hexists" => {
+                    handle! {data, stream, arr, hexists, key, field}
+                }
+                b"hkeys" => {
+                    handle! {data, stream, arr, hkeys, key}
+                }
+                b"hvals" => {
+                    handle! {data, stream, arr, hvals, key}
+                }
+                b"hlen" => {
+                    handle! {data, stream, arr, hlen, key}
+                }
+                b"hscan" => {
+                    handle! {data, stream, arr, hscan, key, cursor; ..}
+                }
+                b"sadd" => {
+                    handle! {data, stream, arr, sadd, key; ..+}
+                }
+                b"srem" => {
+                    handle! {data, stream, arr, srem, key; ..+}
+                }
+                b"sscan" => {
+                    handle! {data, stream, arr, sscan, key, cursor; ..}
+                }
+                b"smembers" => {
+                    handle! {data, stream, arr, smembers, key}
+                }
+                b"sismember" => {
+                    handle! {data, stream, arr, sismember, key, member}
+                }
+                b"smismember" => {
+                    handle! {data, stream, arr, smismember, key; ..+}
+                }
+                b"scard" => {
+                    handle! {data, stream, arr, scard, key}
+                }
+                b"spop" => {
+                    handle! {data, stream, arr, spop, key; ..}
+                }
+                b"srandmember" => {
+                    handle! {data, stream, arr, srandmember, key; ..}
+                }
+                b"smove" => {
+                    handle! {data, stream, arr, smove, source, destination, member}
+                }
+                b"sinter" => {
+                    handle! {data, stream, arr, sinter, key; ..}
+                }
+                b"sunion" => {
+                    handle! {data, stream, arr, sunion, key; ..}
+                }
+                b"sdiff" => {
+                    handle! {data, stream, arr, sdiff, key; ..}
+                }
+                b"sinterstore" => {
+                    handle! {data, stream, arr, sinterstore, destination, key; ..}
+                }
+                b"sunionstore" => {
+                    handle! {data, stream, arr, sunionstore, destination, key; ..}
+                }
+                b"sdiffstore" => {
+                    handle! {data, stream, arr, sdiffstore, destination, key; ..}
+                }
+                b"sintercard" => {
+                    handle! {data, stream, arr, sintercard, numkeys; ..+}
+                }
+                b"zadd" => {
+                    handle! {data, stream, arr, zadd, key; ..+}
+                }
+                b"zincrby" => {
+                    handle! {data, stream, arr, zincrby, key, increment, member}
+                }
+                b"zrem" => {
+                    handle! {data, stream, arr, zrem, key; ..+}
+                }
+                b"zcard" => {
+                    handle! {data, stream, arr, zcard, key}
+                }
+                b"zscore" => {
+                    handle! {data, stream, arr, zscore, key, member}
+                }
+                b"zscan" => {
+                    handle! {data, stream, arr, zscan, key, cursor; ..}
+                }
+                b"zrank" => {
+                    handle! {data, stream, arr, zrank, key, member; ..}
+                }
+                b"zrevrank" => {
+                    handle! {data, stream, arr, zrevrank, key, member; ..}
+                }
+                b"zrange" => {
+                    handle! {data, stream, arr, zrange, key, start, stop; ..}
+                }
+                b"zremrangebyrank" => {
+                    handle! {data, stream, arr, zremrangebyrank, key, start, stop}
+                }
+                b"zremrangebyscore" => {
+                    handle! {data, stream, arr, zremrangebyscore, key, min, max}
+                }
+                b"zremrangebylex" => {
+                    handle! {data, stream, arr, zremrangebylex, key, min, max}
+                }
+                b"zunion" => {
+                    handle! {data, stream, arr, zunion, numkeys; ..+}
+                }
+                b"zinter" => {
+                    handle! {data, stream, arr, zinter, numkeys; ..+}
+                }
+                b"zdiff" => {
+                    handle! {data, stream, arr, zdiff, numkeys; ..+}
+                }
+                b"zunionstore" => {
+                    handle! {data, stream, arr, zunionstore, destination, numkeys; ..+}
+                }
+                b"zinterstore" => {
+                    handle! {data, stream, arr, zinterstore, destination, numkeys; ..+}
+                }
+                b"zdiffstore" => {
+                    handle! {data, stream, arr, zdiffstore, destination, numkeys; ..+}
+                }
+                b"zpopmin" => {
+                    handle! {data, stream, arr, zpopmin, key; ..}
+                }
+                b"zpopmax" => {
+                    handle! {data, stream, arr, zpopmax, key; ..}
+                }
+                b"bzpopmin" => {
+                    handle! {data, stream, arr, bzpopmin, key; ..+}
+                }
+                b"bzpopmax" => {
+                    handle! {data, stream, arr, bzpopmax, key; ..+}
+                }
+                b"geoadd" => {
+                    handle! {data, stream, arr, geoadd, key; ..+}
+                }
+                b"geodist" => {
+                    handle! {data, stream, arr, geodist, key, member1, member2; ..}
+                }
+                b"geopos" => {
+                    handle! {data, stream, arr, geopos, key; ..}
+                }
+                b"geohash" => {
+                    handle! {data, stream, arr, geohash, key; ..}
+                }
+                b"geosearch" => {
+                    handle! {data, stream, arr, geosearch, key; ..+}
+                }
+                b"geosearchstore" => {
+                    handle! {data, stream, arr, geosearchstore, destination, source; ..+}
+                }
+                b"xadd" => {
+                    handle! {data, stream, arr, xadd, key; ..+}
+                }
+                b"xlen" => {
+                    handle! {data, stream, arr, xlen, key}
+                }
+                b"xrange" => {
+                    handle! {data, stream, arr, xrange, key, start, end; ..}
+                }
+                b"xrevrange" => {
+                    handle! {data, stream, arr, xrevrange, key, end, start; ..}
+                }
+                b"xtrim" => {
+                    handle! {data, stream, arr, xtrim, key; ..+}
+                }
+                b"xdel" => {
+                    handle! {data, stream, arr, xdel, key; ..+}
+                }
+                b"xgroup" => {
+                    handle! {data, stream, arr, xgroup, subcommand; ..}
+                }
+                b"xread" => {
+                    handle! {data, stream, arr, xread, ..}
+                }
+                b"xreadgroup" => {
+                    handle! {data, stream, arr, xreadgroup, ..}
+                }
+                b"xack" => {
+                    handle! {data, stream, arr, xack, key, group; ..+}
+                }
+                b"xpending" => {
+                    handle! {data, stream, arr, xpending, key, group; ..}
+                }
+                b"xclaim" => {
+                    handle! {data, stream, arr, xclaim, key, group, consumer, min_idle; ..+}
+                }
+                b"xautoclaim" => {
+                    handle! {data, stream, arr, xautoclaim, key, group, consumer, min_idle; ..+}
+                }
+                b"xinfo" => {
+                    handle! {data, stream, arr, xinfo, subcommand; ..}
+                }
+                b"blmove" => {
+                    handle! {data, stream, arr, blmove, source, destination, wherefrom, whereto, timeout}
+                }
                 _ => send_error(stream, "ERR unknown command"),
             }
         }
@@ -0,0 +1,82 @@
+//! Arguments of the SCAN family. The iteration itself is `Dict::scan`,
+//! whose cursor needs no server side state.
+
+use crate::{NOT_AN_INTEGER, SYNTAX_ERROR, glob::glob_match, util::parse_int};
+
+/// What a command of the SCAN family iterates, which decides the options it
+/// takes: TYPE only makes sense for keys and NOVALUES only for hashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanTarget {
+    Keys,
+    Hash,
+    Set,
+    ZSet,
+}
+
+/// Names of the types the TYPE option of SCAN can filter on.
+const TYPE_NAMES: [&str; 6] = ["string", "list", "set", "zset", "hash", "stream"];
+
+/// Optional arguments shared by SCAN, HSCAN, SSCAN and ZSCAN.
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    pub pattern: Option<Vec<u8>>,
+    pub count: usize,
+    pub novalues: bool,
+    /// Only return keys of this type, as named by TYPE.
+    pub object_type: Option<&'static str>,
+}
+
+impl ScanOptions {
+    pub fn matches(&self, element: &[u8]) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| glob_match(pattern, element, false))
+    }
+}
+
+/// Parses `cursor [MATCH pattern] [COUNT count] [NOVALUES] [TYPE type]`,
+/// accepting only the options that apply to `target`.
+pub fn parse_scan_args(
+    cursor: &[u8],
+    args: &[Vec<u8>],
+    target: ScanTarget,
+) -> Result<(u64, ScanOptions), String> {
+    let cursor = std::str::from_utf8(cursor)
+        .ok()
+        .and_then(|c| c.parse::<u64>().ok())
+        .ok_or("ERR invalid cursor")?;
+    let mut options = ScanOptions {
+        pattern: None,
+        count: 10,
+        novalues: false,
+        object_type: None,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.to_ascii_lowercase().as_slice() {
+            b"match" => options.pattern = Some(args.next().ok_or(SYNTAX_ERROR)?.clone()),
+            b"count" => {
+                let count = parse_int(args.next().ok_or(SYNTAX_ERROR)?).ok_or(NOT_AN_INTEGER)?;
+                if count < 1 {
+                    return Err(SYNTAX_ERROR.to_string());
+                }
+                options.count = count as usize;
+            }
+            b"novalues" if target == ScanTarget::Hash => options.novalues = true,
+            b"novalues" => return Err("ERR NOVALUES option can only be used in HSCAN".to_string()),
+            b"type" if target == ScanTarget::Keys => {
+                let name = args.next().ok_or(SYNTAX_ERROR)?;
+                let lowercase = name.to_ascii_lowercase();
+                let Some(name) = TYPE_NAMES.iter().find(|t| t.as_bytes() == lowercase) else {
+                    return Err(format!(
+                        "ERR unknown type name '{}'",
+                        String::from_utf8_lossy(name)
+                    ));
+                };
+                options.object_type = Some(name);
+            }
+            _ => return Err(SYNTAX_ERROR.to_string()),
+        }
+    }
+    Ok((cursor, options))
+}
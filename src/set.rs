@@ -0,0 +1,159 @@
+use std::borrow::Cow;
+
+use crate::{
+    dict::Dict,
+    util::{parse_int, random_u64},
+};
+
+/// Most members a set keeps in the intset encoding, like Redis'
+/// `set-max-intset-entries`.
+pub const SET_MAX_INTSET_ENTRIES: usize = 512;
+
+/// A set stored in the keyspace.
+///
+/// Sets made only of integers start out as a sorted vector of `i64`, which
+/// is a lot more compact than hashing each member. The first member that is
+/// not an integer, or the one going over `SET_MAX_INTSET_ENTRIES`, turns it
+/// into a hash table for good.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Set {
+    IntSet(Vec<i64>),
+    Hash(Dict<Vec<u8>, ()>),
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Set::IntSet(Vec::new())
+    }
+}
+
+impl Set {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Set::IntSet(ints) => ints.len(),
+            Set::Hash(members) => members.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(ints) => parse_int(member).is_some_and(|n| ints.binary_search(&n).is_ok()),
+            Set::Hash(members) => members.contains_key(member),
+        }
+    }
+
+    /// Adds `member`, returning whether it wasn't there yet.
+    pub fn insert(&mut self, member: Vec<u8>) -> bool {
+        if let Set::IntSet(ints) = self
+            && let Some(n) = parse_int(&member)
+        {
+            let Err(at) = ints.binary_search(&n) else {
+                return false;
+            };
+            if ints.len() < SET_MAX_INTSET_ENTRIES {
+                ints.insert(at, n);
+                return true;
+            }
+        }
+        self.hash_encoded().insert(member, ()).is_none()
+    }
+
+    /// Removes `member`, returning whether it was there.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(ints) => match parse_int(member).map(|n| ints.binary_search(&n)) {
+                Some(Ok(at)) => {
+                    ints.remove(at);
+                    true
+                }
+                _ => false,
+            },
+            Set::Hash(members) => members.remove(member).is_some(),
+        }
+    }
+
+    /// A member picked at random, `None` if the set is empty.
+    pub fn random(&self) -> Option<Cow<'_, [u8]>> {
+        match self {
+            Set::IntSet(ints) if ints.is_empty() => None,
+            Set::IntSet(ints) => {
+                let n = ints[random_u64() as usize % ints.len()];
+                Some(Cow::Owned(n.to_string().into_bytes()))
+            }
+            Set::Hash(members) => members.random().map(|(m, _)| Cow::Borrowed(m.as_slice())),
+        }
+    }
+
+    /// Removes and returns a member picked at random, `None` if the set is
+    /// empty.
+    pub fn pop_random(&mut self) -> Option<Vec<u8>> {
+        let member = self.random()?.into_owned();
+        self.remove(&member);
+        Some(member)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Cow<'_, [u8]>> {
+        let (ints, members) = match self {
+            Set::IntSet(ints) => (Some(ints), None),
+            Set::Hash(members) => (None, Some(members)),
+        };
+        ints.into_iter()
+            .flatten()
+            .map(|n| Cow::Owned(n.to_string().into_bytes()))
+            .chain(
+                members
+                    .into_iter()
+                    .flat_map(Dict::keys)
+                    .map(|m| Cow::Borrowed(m.as_slice())),
+            )
+    }
+
+    /// One SSCAN step, see `Dict::scan`. An intset is small enough to be
+    /// returned whole.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(Cow<'_, [u8]>)) -> u64 {
+        match self {
+            Set::IntSet(ints) => {
+                for n in ints {
+                    visit(Cow::Owned(n.to_string().into_bytes()));
+                }
+                0
+            }
+            Set::Hash(members) => {
+                members.scan(cursor, count, |member, _| visit(Cow::Borrowed(member)))
+            }
+        }
+    }
+
+    /// Switches to the hash table encoding, if not there yet.
+    fn hash_encoded(&mut self) -> &mut Dict<Vec<u8>, ()> {
+        if let Set::IntSet(ints) = self {
+            *self = Set::Hash(
+                ints.iter()
+                    .map(|n| (n.to_string().into_bytes(), ()))
+                    .collect(),
+            );
+        }
+        match self {
+            Set::Hash(members) => members,
+            Set::IntSet(_) => unreachable!(),
+        }
+    }
+}
+
+impl FromIterator<Vec<u8>> for Set {
+    fn from_iter<I: IntoIterator<Item = Vec<u8>>>(iter: I) -> Self {
+        let mut set = Set::new();
+        for member in iter {
+            set.insert(member);
+        }
+        set
+    }
+}
@@ -0,0 +1,585 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    ops::Bound,
+};
+
+use crate::db::now_ms;
+
+/// Entries an approximated (`~`) trim removes at a time, like the
+/// `stream-node-max-entries` radix tree nodes of Redis.
+pub const STREAM_NODE_MAX_ENTRIES: u64 = 100;
+
+pub const INVALID_ID: &str = "ERR Invalid stream ID specified as stream command argument";
+
+/// ID of a stream entry: the milliseconds part plus a sequence number for
+/// the entries added within the same millisecond.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+fn parse_u64(arg: &[u8]) -> Option<u64> {
+    if arg.is_empty() || !arg.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    /// Parses `ms-seq`, or just `ms` with `missing_seq` as the sequence.
+    pub fn parse(arg: &[u8], missing_seq: u64) -> Option<StreamId> {
+        match arg.iter().position(|&b| b == b'-') {
+            Some(dash) => Some(StreamId {
+                ms: parse_u64(&arg[..dash])?,
+                seq: parse_u64(&arg[dash + 1..])?,
+            }),
+            None => Some(StreamId {
+                ms: parse_u64(arg)?,
+                seq: missing_seq,
+            }),
+        }
+    }
+
+    /// The smallest ID after this one.
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_add(1)?,
+                seq: 0,
+            }),
+        }
+    }
+
+    /// The largest ID before this one.
+    pub fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_sub(1)?,
+                seq: u64::MAX,
+            }),
+        }
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+}
+
+impl Display for StreamId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// ID argument of XADD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewId {
+    /// `*`: current time, or the next sequence if the clock went backwards.
+    Auto,
+    /// `ms-*`: the next sequence within the given milliseconds.
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+impl NewId {
+    pub fn parse(arg: &[u8]) -> Option<NewId> {
+        if arg == b"*" {
+            return Some(NewId::Auto);
+        }
+        if let Some(ms) = arg.strip_suffix(b"-*") {
+            return parse_u64(ms).map(NewId::AutoSeq);
+        }
+        StreamId::parse(arg, 0).map(NewId::Explicit)
+    }
+}
+
+/// What to keep when trimming a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimStrategy {
+    /// At most this many entries.
+    MaxLen(u64),
+    /// Only entries with this ID or above.
+    MinId(StreamId),
+}
+
+/// Trimming requested by XADD or XTRIM. An approximated trim only removes
+/// whole nodes of `STREAM_NODE_MAX_ENTRIES` entries, and no more than
+/// `limit` entries (100 nodes by default, 0 for no limit).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trim {
+    pub strategy: TrimStrategy,
+    pub approx: bool,
+    pub limit: Option<u64>,
+}
+
+/// Fields and values of an entry, interleaved.
+pub type Fields = Vec<Vec<u8>>;
+
+/// An entry delivered to a consumer of a group and not acknowledged yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingEntry {
+    pub consumer: Vec<u8>,
+    /// Unix time in milliseconds of the last delivery.
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Consumer {
+    /// Last time the consumer tried to read or claim, in unix milliseconds.
+    pub seen_time: u64,
+    /// Last time it actually got something, if ever.
+    pub active_time: Option<u64>,
+    pub pending: BTreeSet<StreamId>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsumerGroup {
+    pub last_delivered: StreamId,
+    /// Logical position of `last_delivered` among all the entries ever
+    /// added, `None` when it can't be known because of deletions.
+    pub entries_read: Option<u64>,
+    /// The pending entries list, shared by all the consumers.
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    pub consumers: BTreeMap<Vec<u8>, Consumer>,
+}
+
+impl ConsumerGroup {
+    /// The consumer called `name`, created if needed, marked as seen.
+    pub fn consumer(&mut self, name: &[u8], now: u64) -> &mut Consumer {
+        let consumer = self.consumers.entry(name.to_vec()).or_default();
+        consumer.seen_time = now;
+        consumer
+    }
+
+    /// Assigns the pending entry `id` to `consumer`, taking it away from
+    /// whoever had it.
+    fn assign(&mut self, id: StreamId, consumer: &[u8], entry: PendingEntry) {
+        if let Some(old) = self.pending.insert(id, entry)
+            && let Some(owner) = self.consumers.get_mut(&old.consumer)
+        {
+            owner.pending.remove(&id);
+        }
+        self.consumers
+            .entry(consumer.to_vec())
+            .or_default()
+            .pending
+            .insert(id);
+    }
+
+    /// Acknowledges `id`, returning whether it was pending.
+    pub fn ack(&mut self, id: StreamId) -> bool {
+        let Some(entry) = self.pending.remove(&id) else {
+            return false;
+        };
+        if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+            consumer.pending.remove(&id);
+        }
+        true
+    }
+
+    /// Deletes a consumer, returning how many entries it had pending.
+    pub fn remove_consumer(&mut self, name: &[u8]) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+}
+
+/// Options of XCLAIM and XAUTOCLAIM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClaimOptions {
+    /// Only claim entries idle for at least this many milliseconds.
+    pub min_idle: u64,
+    /// New delivery time of the claimed entries.
+    pub delivery_time: u64,
+    /// New delivery count, instead of adding one to it.
+    pub retry_count: Option<u64>,
+    /// Create the pending entry for IDs that exist but aren't pending.
+    pub force: bool,
+    /// Leave the delivery count alone, the client only wants the IDs.
+    pub justid: bool,
+}
+
+/// A stream: entries ordered by ID, which only ever grows, plus the consumer
+/// groups reading from it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    last_id: StreamId,
+    max_deleted_id: StreamId,
+    entries_added: u64,
+    groups: BTreeMap<Vec<u8>, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// ID of the last entry ever added, even if it was deleted since.
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Largest ID among the deleted entries.
+    pub fn max_deleted_id(&self) -> StreamId {
+        self.max_deleted_id
+    }
+
+    /// Amount of entries ever added, including the deleted ones.
+    pub fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    pub fn first_entry(&self) -> Option<(StreamId, &Fields)> {
+        self.entries.first_key_value().map(|(id, f)| (*id, f))
+    }
+
+    pub fn last_entry(&self) -> Option<(StreamId, &Fields)> {
+        self.entries.last_key_value().map(|(id, f)| (*id, f))
+    }
+
+    pub fn get(&self, id: StreamId) -> Option<&Fields> {
+        self.entries.get(&id)
+    }
+
+    /// Resolves the ID a new entry would get, failing if it isn't above the
+    /// last one.
+    pub fn next_id(&self, id: NewId) -> Result<StreamId, &'static str> {
+        const TOO_SMALL: &str =
+            "ERR The ID specified in XADD is equal or smaller than the target stream top item";
+        let last = self.last_id;
+        let id = match id {
+            NewId::Auto => {
+                let ms = now_ms();
+                if ms > last.ms {
+                    StreamId { ms, seq: 0 }
+                } else {
+                    last.next().ok_or(
+                        "ERR The stream has exhausted the last possible ID, unable to add more items",
+                    )?
+                }
+            }
+            NewId::AutoSeq(ms) if ms == last.ms && last != StreamId::MIN => StreamId {
+                ms,
+                seq: last.seq.checked_add(1).ok_or(TOO_SMALL)?,
+            },
+            NewId::AutoSeq(ms) => StreamId {
+                ms,
+                seq: (ms == 0) as u64,
+            },
+            NewId::Explicit(id) => id,
+        };
+        if id == StreamId::MIN {
+            return Err("ERR The ID specified in XADD must be greater than 0-0");
+        }
+        if id <= last {
+            return Err(TOO_SMALL);
+        }
+        Ok(id)
+    }
+
+    /// Appends an entry, whose ID has to come from `next_id`.
+    pub fn add(&mut self, id: StreamId, fields: Fields) {
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    pub fn remove(&mut self, id: StreamId) -> bool {
+        if self.entries.remove(&id).is_none() {
+            return false;
+        }
+        self.max_deleted_id = self.max_deleted_id.max(id);
+        true
+    }
+
+    /// Entries between `start` and `end` inclusive, from the end backwards
+    /// if `rev` is set.
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        rev: bool,
+    ) -> Box<dyn Iterator<Item = (StreamId, &Fields)> + '_> {
+        if start > end {
+            return Box::new(std::iter::empty());
+        }
+        let range = self
+            .entries
+            .range((Bound::Included(start), Bound::Included(end)))
+            .map(|(id, f)| (*id, f));
+        if rev {
+            Box::new(range.rev())
+        } else {
+            Box::new(range)
+        }
+    }
+
+    /// Removes old entries as asked by `trim`, returning how many went away.
+    pub fn trim(&mut self, trim: &Trim) -> usize {
+        let mut count = match trim.strategy {
+            TrimStrategy::MaxLen(max) => self.entries.len().saturating_sub(max as usize),
+            TrimStrategy::MinId(min) => self.entries.range(..min).count(),
+        };
+        if trim.approx {
+            match trim.limit.unwrap_or(100 * STREAM_NODE_MAX_ENTRIES) {
+                0 => {}
+                limit => count = count.min(limit as usize),
+            }
+            count -= count % STREAM_NODE_MAX_ENTRIES as usize;
+        }
+        for _ in 0..count {
+            self.entries.pop_first();
+        }
+        count
+    }
+
+    /// Whether an entry between `start` and `end` inclusive was deleted.
+    fn has_tombstones(&self, start: StreamId, end: StreamId) -> bool {
+        !self.entries.is_empty()
+            && self.max_deleted_id != StreamId::MIN
+            && start <= self.max_deleted_id
+            && self.max_deleted_id <= end
+    }
+
+    /// How many entries were ever added up to `id` included, if that can be
+    /// told despite deletions.
+    pub fn entries_read_at(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if self.entries.is_empty() && id <= self.last_id {
+            return Some(self.entries_added);
+        }
+        match id.cmp(&self.last_id) {
+            std::cmp::Ordering::Equal => return Some(self.entries_added),
+            std::cmp::Ordering::Greater => return None,
+            std::cmp::Ordering::Less => {}
+        }
+        let first = self.first_entry().map_or(StreamId::MIN, |(id, _)| id);
+        if self.max_deleted_id == StreamId::MIN || self.max_deleted_id < first {
+            let before_first = self.entries_added - self.entries.len() as u64;
+            match id.cmp(&first) {
+                std::cmp::Ordering::Less => return Some(before_first),
+                std::cmp::Ordering::Equal => return Some(before_first + 1),
+                std::cmp::Ordering::Greater => {}
+            }
+        }
+        None
+    }
+
+    /// Entries added after the last one delivered to `group` that it has
+    /// yet to read, if that can be told.
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if self.has_tombstones(group.last_delivered, StreamId::MAX) {
+            return None;
+        }
+        group
+            .entries_read
+            .or_else(|| self.entries_read_at(group.last_delivered))
+            .map(|read| self.entries_added.saturating_sub(read))
+    }
+
+    pub fn groups(&self) -> &BTreeMap<Vec<u8>, ConsumerGroup> {
+        &self.groups
+    }
+
+    pub fn group(&self, name: &[u8]) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// Creates a group that will deliver the entries after `last_delivered`,
+    /// returning false if there is one with that name already.
+    pub fn create_group(
+        &mut self,
+        name: &[u8],
+        last_delivered: StreamId,
+        entries_read: Option<u64>,
+    ) -> bool {
+        if self.groups.contains_key(name) {
+            return false;
+        }
+        let entries_read = entries_read.or_else(|| self.entries_read_at(last_delivered));
+        self.groups.insert(
+            name.to_vec(),
+            ConsumerGroup {
+                last_delivered,
+                entries_read,
+                ..Default::default()
+            },
+        );
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Moves the last delivered ID of a group, returning false if there is
+    /// no such group.
+    pub fn set_group_id(
+        &mut self,
+        name: &[u8],
+        last_delivered: StreamId,
+        entries_read: Option<u64>,
+    ) -> bool {
+        let entries_read = entries_read.or_else(|| self.entries_read_at(last_delivered));
+        let Some(group) = self.groups.get_mut(name) else {
+            return false;
+        };
+        group.last_delivered = last_delivered;
+        group.entries_read = entries_read;
+        true
+    }
+
+    /// Delivers to `consumer` up to `count` entries the group hasn't
+    /// delivered yet, adding them to the pending entries list unless `noack`
+    /// is set. `None` if there is no such group.
+    pub fn read_group_new(
+        &mut self,
+        group: &[u8],
+        consumer: &[u8],
+        count: usize,
+        noack: bool,
+    ) -> Option<Vec<(StreamId, Fields)>> {
+        let now = now_ms();
+        let old = self.groups.get(group)?;
+        let entries: Vec<(StreamId, Fields)> = self
+            .entries
+            .range((Bound::Excluded(old.last_delivered), Bound::Unbounded))
+            .take(count)
+            .map(|(id, fields)| (*id, fields.clone()))
+            .collect();
+        let Some(&(last, _)) = entries.last() else {
+            self.groups.get_mut(group)?.consumer(consumer, now);
+            return Some(entries);
+        };
+        let entries_read = match old.entries_read {
+            Some(_) if last == self.last_id => Some(self.entries_added),
+            Some(read) if !self.has_tombstones(old.last_delivered, last) => {
+                Some(read + entries.len() as u64)
+            }
+            _ => self.entries_read_at(last),
+        };
+        let group = self.groups.get_mut(group)?;
+        group.last_delivered = last;
+        group.entries_read = entries_read;
+        group.consumer(consumer, now).active_time = Some(now);
+        if !noack {
+            for (id, _) in &entries {
+                let pending = PendingEntry {
+                    consumer: consumer.to_vec(),
+                    delivery_time: now,
+                    delivery_count: 1,
+                };
+                group.assign(*id, consumer, pending);
+            }
+        }
+        Some(entries)
+    }
+
+    /// Delivers again up to `count` of the entries pending for `consumer`
+    /// with an ID above `after`. Entries deleted since come without fields.
+    /// `None` if there is no such group.
+    pub fn read_group_pending(
+        &mut self,
+        group: &[u8],
+        consumer: &[u8],
+        after: StreamId,
+        count: usize,
+    ) -> Option<Vec<(StreamId, Option<Fields>)>> {
+        let now = now_ms();
+        let group = self.groups.get_mut(group)?;
+        let ids: Vec<StreamId> = group
+            .consumer(consumer, now)
+            .pending
+            .range((Bound::Excluded(after), Bound::Unbounded))
+            .take(count)
+            .copied()
+            .collect();
+        let mut entries = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(pending) = group.pending.get_mut(&id) {
+                pending.delivery_time = now;
+                pending.delivery_count += 1;
+            }
+            entries.push((id, self.entries.get(&id).cloned()));
+        }
+        Some(entries)
+    }
+
+    /// Claims the pending entry `id` for `consumer` if it's idle enough,
+    /// returning its fields. Entries deleted from the stream are dropped
+    /// from the pending list instead, and reported with `Err`.
+    pub fn claim(
+        &mut self,
+        group: &[u8],
+        consumer: &[u8],
+        id: StreamId,
+        options: &ClaimOptions,
+        now: u64,
+    ) -> Result<Option<Fields>, StreamId> {
+        let Some(group) = self.groups.get_mut(group) else {
+            return Ok(None);
+        };
+        let fields = self.entries.get(&id);
+        let current = match group.pending.get(&id) {
+            Some(_) if fields.is_none() => {
+                group.ack(id);
+                return Err(id);
+            }
+            Some(pending) => pending.clone(),
+            None if options.force && fields.is_some() => PendingEntry {
+                consumer: consumer.to_vec(),
+                delivery_time: now,
+                delivery_count: 0,
+            },
+            None => return Ok(None),
+        };
+        if now.saturating_sub(current.delivery_time) < options.min_idle {
+            return Ok(None);
+        }
+        let delivery_count = match options.retry_count {
+            Some(count) => count,
+            None if options.justid => current.delivery_count,
+            None => current.delivery_count + 1,
+        };
+        let pending = PendingEntry {
+            consumer: consumer.to_vec(),
+            delivery_time: options.delivery_time,
+            delivery_count,
+        };
+        group.assign(id, consumer, pending);
+        group.consumer(consumer, now).active_time = Some(now);
+        Ok(fields.cloned())
+    }
+}
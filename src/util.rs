@@ -0,0 +1,70 @@
+use std::hash::{BuildHasher, Hasher, RandomState};
+
+/// Parses a signed 64 bit integer the way Redis' `string2ll` does: an
+/// optional minus sign followed by digits, no leading zeros, no plus sign and
+/// no surrounding whitespace.
+pub fn parse_int(arg: &[u8]) -> Option<i64> {
+    let digits = arg.strip_prefix(b"-").unwrap_or(arg);
+    if digits.is_empty()
+        || !digits.iter().all(u8::is_ascii_digit)
+        || (digits[0] == b'0' && arg.len() > 1)
+    {
+        return None;
+    }
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+/// Parses a double the way Redis reads floating point arguments: any
+/// decimal or exponent notation plus `inf`/`-inf`, but never NaN.
+pub fn parse_float(arg: &[u8]) -> Option<f64> {
+    let s = std::str::from_utf8(arg).ok()?;
+    s.parse::<f64>().ok().filter(|f| !f.is_nan())
+}
+
+/// Significant decimal digits an `f64` always holds exactly, C's `DBL_DIG`.
+const DBL_DIG: usize = 15;
+
+/// Formats a finite float the way Redis' `ld2string` does in its human mode
+/// for INCRBYFLOAT: plain decimal notation with at most 17 decimals, never
+/// an exponent, without trailing zeros.
+///
+/// Redis computes in long double, whose 17 printed digits hide the rounding
+/// noise of binary fractions. The arithmetic here is `f64`, so it is cut at
+/// `DBL_DIG` significant digits instead to get the same effect: `0.1 + 0.2`
+/// comes out as `0.3`.
+pub fn format_human_float(value: f64) -> String {
+    if value == 0.0 {
+        return "0".to_string();
+    }
+    let scientific = format!("{:.*e}", DBL_DIG - 1, value);
+    let exponent: i64 = scientific.split_once('e').unwrap().1.parse().unwrap();
+    let decimals = (DBL_DIG as i64 - 1 - exponent).clamp(0, 17) as usize;
+    let mut out = format!("{:.*}", decimals, value);
+    if out.contains('.') {
+        out.truncate(out.trim_end_matches('0').trim_end_matches('.').len());
+    }
+    out
+}
+
+/// A random number for picking random elements. Every `RandomState` is
+/// seeded differently, so hashing nothing with a fresh one is enough here.
+pub fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Clamps an inclusive `start..=stop` range with possibly negative bounds to
+/// a list or sorted set of `len` elements, `None` if nothing is left of it.
+pub fn normalize_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (start + len).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        stop + len
+    } else {
+        stop.min(len - 1)
+    };
+    (start <= stop && start < len).then_some((start as usize, stop as usize))
+}
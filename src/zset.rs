@@ -0,0 +1,510 @@
+use std::ops::Range;
+
+use crate::{
+    MyFloat,
+    dict::Dict,
+    util::{parse_float, random_u64},
+};
+
+/// Most levels a skiplist node can have, plenty for 2^64 elements at p=1/4.
+const MAX_LEVEL: usize = 32;
+
+/// Index of the header node, which holds no element.
+const HEAD: usize = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Link {
+    next: Option<usize>,
+    /// Amount of level 0 steps this link skips over, used to compute ranks.
+    span: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Node {
+    member: Vec<u8>,
+    score: MyFloat,
+    backward: Option<usize>,
+    levels: Vec<Link>,
+}
+
+impl Node {
+    /// Whether this node sorts before `(score, member)`.
+    fn is_before(&self, score: &MyFloat, member: &[u8]) -> bool {
+        (&self.score, self.member.as_slice()) < (score, member)
+    }
+}
+
+/// The skiplist of Redis' `t_zset.c`, ordered by score and then member, with
+/// spans on every link so that ranks can be found in logarithmic time.
+/// Nodes live in an arena and point to each other by index.
+#[derive(Debug, Clone, PartialEq)]
+struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: Option<usize>,
+    level: usize,
+    len: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        let head = Node {
+            member: Vec::new(),
+            score: MyFloat::Real(0.0),
+            backward: None,
+            levels: vec![
+                Link {
+                    next: None,
+                    span: 0
+                };
+                MAX_LEVEL
+            ],
+        };
+        Self {
+            nodes: vec![head],
+            free: Vec::new(),
+            tail: None,
+            level: 1,
+            len: 0,
+        }
+    }
+}
+
+fn random_level() -> usize {
+    let mut level = 1;
+    while level < MAX_LEVEL && random_u64().is_multiple_of(4) {
+        level += 1;
+    }
+    level
+}
+
+impl SkipList {
+    fn next(&self, node: usize, level: usize) -> Option<usize> {
+        self.nodes[node].levels[level].next
+    }
+
+    /// The last node of every level that sorts before `(score, member)`.
+    fn predecessors(
+        &self,
+        score: &MyFloat,
+        member: &[u8],
+    ) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            while let Some(next) = self.next(x, i)
+                && self.nodes[next].is_before(score, member)
+            {
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+        (update, rank)
+    }
+
+    /// Adds an element, which must not be in the list yet.
+    fn insert(&mut self, score: MyFloat, member: Vec<u8>) {
+        let (mut update, mut rank) = self.predecessors(&score, &member);
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+        let node = Node {
+            member,
+            score,
+            backward: (update[0] != HEAD).then_some(update[0]),
+            levels: vec![
+                Link {
+                    next: None,
+                    span: 0
+                };
+                level
+            ],
+        };
+        let x = match self.free.pop() {
+            Some(x) => {
+                self.nodes[x] = node;
+                x
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        for i in 0..level {
+            let prev = self.nodes[update[i]].levels[i];
+            self.nodes[x].levels[i] = Link {
+                next: prev.next,
+                span: prev.span - (rank[0] - rank[i]),
+            };
+            self.nodes[update[i]].levels[i] = Link {
+                next: Some(x),
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        for (i, &prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[prev].levels[i].span += 1;
+        }
+        match self.next(x, 0) {
+            Some(next) => self.nodes[next].backward = Some(x),
+            None => self.tail = Some(x),
+        }
+        self.len += 1;
+    }
+
+    /// Removes the element, returning whether it was there.
+    fn remove(&mut self, score: &MyFloat, member: &[u8]) -> bool {
+        let (update, _) = self.predecessors(score, member);
+        let Some(x) = self.next(update[0], 0) else {
+            return false;
+        };
+        if self.nodes[x].score != *score || self.nodes[x].member != member {
+            return false;
+        }
+        for (i, &prev) in update.iter().enumerate().take(self.level) {
+            if self.next(prev, i) == Some(x) {
+                let link = self.nodes[x].levels[i];
+                self.nodes[prev].levels[i] = Link {
+                    next: link.next,
+                    span: self.nodes[prev].levels[i].span + link.span - 1,
+                };
+            } else {
+                self.nodes[prev].levels[i].span -= 1;
+            }
+        }
+        match self.next(x, 0) {
+            Some(next) => self.nodes[next].backward = self.nodes[x].backward,
+            None => self.tail = self.nodes[x].backward,
+        }
+        while self.level > 1 && self.next(HEAD, self.level - 1).is_none() {
+            self.level -= 1;
+        }
+        self.nodes[x].member = Vec::new();
+        self.nodes[x].levels = Vec::new();
+        self.free.push(x);
+        self.len -= 1;
+        true
+    }
+
+    /// 0-based rank of the element, if it's in the list.
+    fn rank(&self, score: &MyFloat, member: &[u8]) -> Option<usize> {
+        let (update, rank) = self.predecessors(score, member);
+        let x = self.next(update[0], 0)?;
+        let node = &self.nodes[x];
+        (node.score == *score && node.member == member).then_some(rank[0])
+    }
+
+    /// Node at the 0-based `rank`.
+    fn by_rank(&self, rank: usize) -> Option<usize> {
+        if rank >= self.len {
+            return None;
+        }
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.next(x, i)
+                && traversed + self.nodes[x].levels[i].span <= target
+            {
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    /// Amount of elements at the head of the list for which `pred` holds.
+    /// `pred` has to be true for a prefix of the list and false after it.
+    fn count_while(&self, pred: impl Fn(&MyFloat, &[u8]) -> bool) -> usize {
+        let mut count = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.next(x, i)
+                && pred(&self.nodes[next].score, &self.nodes[next].member)
+            {
+                count += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        count
+    }
+}
+
+/// Walks a sorted set in either direction, starting at some rank.
+pub struct Iter<'a> {
+    list: &'a SkipList,
+    node: Option<usize>,
+    rev: bool,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a [u8], f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = &self.list.nodes[self.node?];
+        self.node = if self.rev {
+            node.backward
+        } else {
+            node.levels[0].next
+        };
+        Some((&node.member, score_value(&node.score)))
+    }
+}
+
+fn score_value(score: &MyFloat) -> f64 {
+    match score {
+        MyFloat::Real(f) => *f,
+        MyFloat::NaN => f64::NAN,
+    }
+}
+
+/// A sorted set: the skiplist keeps elements ordered and the hash table maps
+/// members to their score, so that lookups by member don't need a walk.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortedSet {
+    scores: Dict<Vec<u8>, MyFloat>,
+    list: SkipList,
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).map(score_value)
+    }
+
+    /// Sets the score of `member`, adding it if needed. Returns whether it
+    /// was added.
+    pub fn insert(&mut self, member: Vec<u8>, score: f64) -> bool {
+        let score = MyFloat::Real(score);
+        match self.scores.get_mut(&member) {
+            Some(old) if *old == score => false,
+            Some(old) => {
+                let old = std::mem::replace(old, score.clone());
+                self.list.remove(&old, &member);
+                self.list.insert(score, member);
+                false
+            }
+            None => {
+                self.scores.insert(member.clone(), score.clone());
+                self.list.insert(score, member);
+                true
+            }
+        }
+    }
+
+    /// Removes `member`, returning its score if it was there.
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+        self.list.remove(&score, member);
+        Some(score_value(&score))
+    }
+
+    /// 0-based position of `member` in ascending order.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.scores.get(member)?;
+        self.list.rank(score, member)
+    }
+
+    /// Elements from the 0-based `rank` on, towards the highest scores, or
+    /// towards the lowest ones if `rev` is set.
+    pub fn iter_from(&self, rank: usize, rev: bool) -> Iter<'_> {
+        Iter {
+            list: &self.list,
+            node: self.list.by_rank(rank),
+            rev,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        self.iter_from(0, false)
+    }
+
+    /// One ZSCAN step, see `Dict::scan`.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(&[u8], f64)) -> u64 {
+        self.scores.scan(cursor, count, |member, score| {
+            visit(member, score_value(score))
+        })
+    }
+
+    /// Ascending ranks of the elements whose score is within `range`.
+    pub fn score_range(&self, range: &ScoreRange) -> Range<usize> {
+        let start = self
+            .list
+            .count_while(|score, _| !range.min.below(score_value(score)));
+        let end = self
+            .list
+            .count_while(|score, _| range.max.above(score_value(score)));
+        start..end.max(start)
+    }
+
+    /// Ascending ranks of the elements within the lexicographical `range`.
+    /// Only meaningful when all elements have the same score.
+    pub fn lex_range(&self, range: &LexRange) -> Range<usize> {
+        let start = self.list.count_while(|_, member| !range.min.below(member));
+        let end = self.list.count_while(|_, member| range.max.above(member));
+        start..end.max(start)
+    }
+
+    /// Removes and returns the element with the lowest score, or the one
+    /// with the highest if `max` is set.
+    pub fn pop(&mut self, max: bool) -> Option<(Vec<u8>, f64)> {
+        let rank = if max { self.len().checked_sub(1)? } else { 0 };
+        let (member, score) = self
+            .iter_from(rank, false)
+            .next()
+            .map(|(member, score)| (member.to_vec(), score))?;
+        self.remove(&member);
+        Some((member, score))
+    }
+
+    /// Removes the elements within the ascending `ranks`, returning how many
+    /// went away.
+    pub fn remove_range(&mut self, ranks: Range<usize>) -> usize {
+        let members: Vec<Vec<u8>> = self
+            .iter_from(ranks.start, false)
+            .take(ranks.len())
+            .map(|(member, _)| member.to_vec())
+            .collect();
+        for member in &members {
+            self.remove(member);
+        }
+        members.len()
+    }
+}
+
+/// One end of a score interval, `(` makes it exclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreBound {
+    pub value: f64,
+    pub exclusive: bool,
+}
+
+impl ScoreBound {
+    pub fn parse(arg: &[u8]) -> Option<ScoreBound> {
+        let (arg, exclusive) = match arg.strip_prefix(b"(") {
+            Some(arg) => (arg, true),
+            None => (arg, false),
+        };
+        Some(ScoreBound {
+            value: parse_float(arg)?,
+            exclusive,
+        })
+    }
+
+    /// Whether `score` is beyond this bound taken as a minimum.
+    fn below(&self, score: f64) -> bool {
+        if self.exclusive {
+            score > self.value
+        } else {
+            score >= self.value
+        }
+    }
+
+    /// Whether `score` is within this bound taken as a maximum.
+    fn above(&self, score: f64) -> bool {
+        if self.exclusive {
+            score < self.value
+        } else {
+            score <= self.value
+        }
+    }
+}
+
+/// Score interval of ZRANGE BYSCORE and friends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreRange {
+    pub min: ScoreBound,
+    pub max: ScoreBound,
+}
+
+impl ScoreRange {
+    pub fn parse(min: &[u8], max: &[u8]) -> Result<ScoreRange, &'static str> {
+        match (ScoreBound::parse(min), ScoreBound::parse(max)) {
+            (Some(min), Some(max)) => Ok(ScoreRange { min, max }),
+            _ => Err("ERR min or max is not a float"),
+        }
+    }
+
+    pub fn contains(&self, score: f64) -> bool {
+        self.min.below(score) && self.max.above(score)
+    }
+}
+
+/// One end of a lexicographical interval: `-`, `+`, `[member` or `(member`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexBound {
+    Min,
+    Max,
+    Inclusive(Vec<u8>),
+    Exclusive(Vec<u8>),
+}
+
+impl LexBound {
+    pub fn parse(arg: &[u8]) -> Option<LexBound> {
+        match arg {
+            b"-" => Some(LexBound::Min),
+            b"+" => Some(LexBound::Max),
+            [b'[', rest @ ..] => Some(LexBound::Inclusive(rest.to_vec())),
+            [b'(', rest @ ..] => Some(LexBound::Exclusive(rest.to_vec())),
+            _ => None,
+        }
+    }
+
+    /// Whether `member` is beyond this bound taken as a minimum.
+    fn below(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(m) => member >= m.as_slice(),
+            LexBound::Exclusive(m) => member > m.as_slice(),
+        }
+    }
+
+    /// Whether `member` is within this bound taken as a maximum.
+    fn above(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(m) => member <= m.as_slice(),
+            LexBound::Exclusive(m) => member < m.as_slice(),
+        }
+    }
+}
+
+/// Member interval of ZRANGE BYLEX and friends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexRange {
+    pub min: LexBound,
+    pub max: LexBound,
+}
+
+impl LexRange {
+    pub fn parse(min: &[u8], max: &[u8]) -> Result<LexRange, &'static str> {
+        match (LexBound::parse(min), LexBound::parse(max)) {
+            (Some(min), Some(max)) => Ok(LexRange { min, max }),
+            _ => Err("ERR min or max not valid string range item"),
+        }
+    }
+}